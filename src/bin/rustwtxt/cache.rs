@@ -1,16 +1,185 @@
-type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
-
-pub fn get_remote_modtime(url: &str) -> Result<String> {
-    let client = reqwest::Client::new();
-    let resp = client.head(url).send()?;
-    let headers = resp.headers();
-
-    if headers.contains_key("Last-Modified") {
-        match headers.get("Last-Modified") {
-            Some(val) => return Ok(val.to_str()?.into()),
-            None => return Err("Last-Modified Header Empty".into()),
-        };
-    }
+//
+// Copies of followed feeds, kept in $HOME/.cache/rustweet so that feeds
+// which haven't changed aren't downloaded again.
+//
+use rustwtxt::fetch::{
+    self, ClientIdentity, FetchOutcome, Fetched, Fetcher, FileFetcher, GeminiFetcher,
+    GopherFetcher, HttpFetcher, Redirect, Registry,
+};
+use rustwtxt::Twtxt;
+use serde::{Deserialize, Serialize};
+
+use std::fs;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::conf;
+use crate::user;
+
+lazy_static! {
+    static ref HOME: String = std::env::var("HOME").unwrap_or_else(|_| ".".into());
+    // Every fetcher, archives included, is held to the limits in the
+    // config. Requests over HTTP name us as the follower, and Gemini
+    // certificates are remembered in $HOME/.config/rustweet_known_hosts.
+    static ref FETCHER: Arc<Registry> = {
+        let mut registry = Registry::default();
+        let options = conf::DATA.fetch_options();
+        let http =
+            HttpFetcher::with_identity(ClientIdentity::new(&conf::DATA.nick, &conf::DATA.url))
+                .with_options(options);
+        registry.register("http", http.clone());
+        registry.register("https", http);
+        registry.register("gopher", GopherFetcher::new().with_options(options));
+        registry.register("file", FileFetcher::new().with_options(options));
+        let known_hosts = format!("{}/.config/rustweet_known_hosts", *HOME);
+        let gemini = GeminiFetcher::with_known_hosts(&known_hosts).unwrap_or_else(|err| {
+            eprintln!("Can't read {}: {}", known_hosts, err);
+            GeminiFetcher::new()
+        });
+        registry.register("gemini", gemini.with_options(options));
+        Arc::new(registry)
+    };
+    static ref DIR: String = format!("{}/.cache/rustweet", *HOME);
+}
+
+// Stored next to each cached feed, so that only new tweets are
+// downloaded from feeds that have changed. The redirects followed to
+// reach the feed say which URL the validators came from.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Validators {
+    etag: Option<String>,
+    last_modified: Option<String>,
+    #[serde(default)]
+    redirects: Vec<(u16, String, String)>,
+}
+
+// How many feeds are downloaded at once, and how long to wait for them all.
+const CONCURRENCY: usize = 8;
+const DEADLINE: Duration = Duration::from_secs(30);
+
+// The fetcher every request goes through, archives included.
+pub fn fetcher() -> &'static Registry {
+    &FETCHER
+}
+
+// Fetches and parses a feed, using the cached copy if it's still current.
+pub fn twtxt(url: &str) -> rustwtxt::Result<Twtxt> {
+    let cached = load(url);
+    let outcome = FETCHER.fetch(url, cached.as_ref());
+    parse(url, cached, outcome)
+}
+
+// Where a feed that has moved for good now lives, going by the redirects
+// recorded with its cached copy.
+pub fn moved_to(url: &str) -> Option<String> {
+    load(url)?.moved_to().map(String::from)
+}
+
+// Fetches and parses several feeds at once, in the order given.
+pub fn twtxts(urls: &[String]) -> Vec<(String, rustwtxt::Result<Twtxt>)> {
+    let feeds = urls
+        .iter()
+        .map(|url| (url.clone(), load(url)))
+        .collect::<Vec<(String, Option<Fetched>)>>();
+
+    fetch::fetch_many_with(FETCHER.clone(), feeds, CONCURRENCY, DEADLINE)
+        .into_iter()
+        .map(|(url, outcome)| {
+            // Only read from disk again for the feeds that haven't changed.
+            let cached = match outcome {
+                Ok(FetchOutcome::NotModified) => load(&url),
+                _ => None,
+            };
+            let twtxt = parse(&url, cached, outcome);
+            (url, twtxt)
+        })
+        .collect()
+}
 
-    Err("Last-Modified Not Found in Response Headers".into())
+// Reports feeds that have moved for good, marks those that are gone as
+// dead, and keeps only the newest tweets the config allows.
+fn parse(
+    url: &str,
+    cached: Option<Fetched>,
+    outcome: rustwtxt::Result<FetchOutcome>,
+) -> rustwtxt::Result<Twtxt> {
+    let outcome = outcome.map_err(|err| {
+        if let rustwtxt::Error::Http { status: 410, .. } = err {
+            user::gone(url);
+        }
+        err
+    })?;
+    let body = match outcome {
+        FetchOutcome::Modified(fetched) => {
+            if let Some(to) = fetched.moved_to() {
+                user::moved(url, to);
+            }
+            store(url, &fetched);
+            fetched.body
+        }
+        FetchOutcome::NotModified => cached.map(|cached| cached.body).unwrap_or_default(),
+    };
+    let mut twtxt = Twtxt::parse(&body, url)?;
+    twtxt.keep_newest(FETCHER.options(url).max_tweets);
+    Ok(twtxt)
+}
+
+fn paths(url: &str) -> (String, String) {
+    let name = url
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '.' || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect::<String>();
+    (
+        format!("{}/{}.txt", *DIR, name),
+        format!("{}/{}.yaml", *DIR, name),
+    )
+}
+
+fn load(url: &str) -> Option<Fetched> {
+    let (body_path, meta_path) = paths(url);
+    let body = fs::read_to_string(body_path).ok()?;
+    let meta = fs::read_to_string(meta_path).ok()?;
+    let validators = serde_yaml::from_str::<Validators>(&meta).ok()?;
+
+    Some(Fetched {
+        body,
+        etag: validators.etag,
+        last_modified: validators.last_modified,
+        redirects: validators
+            .redirects
+            .into_iter()
+            .map(|(status, from, to)| Redirect { status, from, to })
+            .collect(),
+    })
+}
+
+fn store(url: &str, fetched: &Fetched) {
+    let (body_path, meta_path) = paths(url);
+    let validators = Validators {
+        etag: fetched.etag.clone(),
+        last_modified: fetched.last_modified.clone(),
+        redirects: fetched
+            .redirects
+            .iter()
+            .map(|redirect| (redirect.status, redirect.from.clone(), redirect.to.clone()))
+            .collect(),
+    };
+    let stored = fs::create_dir_all(&*DIR)
+        .map_err(|err| err.to_string())
+        .and_then(|_| serde_yaml::to_string(&validators).map_err(|err| err.to_string()))
+        .and_then(|meta| {
+            fs::write(&body_path, &fetched.body)
+                .and_then(|_| fs::write(&meta_path, meta))
+                .map_err(|err| err.to_string())
+        });
+
+    if let Err(err) = stored {
+        eprintln!("Couldn't cache {}: {}", url, err);
+    }
 }
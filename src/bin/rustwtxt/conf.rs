@@ -2,11 +2,12 @@
 // rustweet - Copyright (c) 2019 Ben Morrison (gbmor)
 // See LICENSE file for detailed license information.
 //
+use rustwtxt::FetchOptions;
 use serde::{Deserialize, Serialize};
-use serde_yaml;
 
 use std::fs;
 use std::process;
+use std::time::Duration;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Data {
@@ -14,6 +15,47 @@ pub struct Data {
     pub path: String,
     pub url: String,
     pub follow: Vec<String>,
+    // Follow feeds that have permanently moved at their new URL.
+    #[serde(default)]
+    pub rewrite_moved: bool,
+    // URLs of followed feeds that are gone for good, which aren't fetched.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub dead: Vec<String>,
+    // Limits on each followed feed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limits: Option<Limits>,
+}
+
+// Any limit left out keeps its default.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct Limits {
+    // In bytes.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_body_size: Option<usize>,
+    // In seconds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub read_timeout: Option<u64>,
+    // In bytes.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_line_length: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_tweets: Option<usize>,
+}
+
+impl Data {
+    pub fn fetch_options(&self) -> FetchOptions {
+        let limits = self.limits.unwrap_or_default();
+        let defaults = FetchOptions::default();
+        FetchOptions {
+            max_body_size: limits.max_body_size.unwrap_or(defaults.max_body_size),
+            read_timeout: limits
+                .read_timeout
+                .map(Duration::from_secs)
+                .unwrap_or(defaults.read_timeout),
+            max_line_length: limits.max_line_length.unwrap_or(defaults.max_line_length),
+            max_tweets: limits.max_tweets.unwrap_or(defaults.max_tweets),
+        }
+    }
 }
 
 lazy_static! {
@@ -64,3 +106,12 @@ pub fn init() -> Data {
         }
     }
 }
+
+pub fn save(data: &Data) {
+    let saved = serde_yaml::to_string(data)
+        .map_err(|err| format!("{:?}", err))
+        .and_then(|yaml| fs::write(&*FILE, yaml).map_err(|err| format!("{:?}", err)));
+    if let Err(err) = saved {
+        eprintln!("Couldn't rewrite config file: {}", err);
+    }
+}
@@ -9,7 +9,7 @@ use crate::conf;
 lazy_static! {
     static ref VAR: String = match env::var("EDITOR") {
         Ok(ed) => {
-            if &ed == "" {
+            if ed.is_empty() {
                 "nano".into()
             } else {
                 ed
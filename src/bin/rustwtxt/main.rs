@@ -36,6 +36,26 @@ fn main() {
             clap::SubCommand::with_name("tweet")
                 .about("Opens your preferred editor to compose a new tweet."),
         )
+        .subcommand(
+            clap::SubCommand::with_name("thread")
+                .about("Displays the conversation containing a tweet.")
+                .arg(
+                    clap::Arg::with_name("hash")
+                        .value_name("HASH")
+                        .required(true)
+                        .help("Twt hash of any tweet in the conversation."),
+                ),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("history")
+                .about("Displays everything a user has posted, including archived tweets.")
+                .arg(
+                    clap::Arg::with_name("nick")
+                        .value_name("NICK")
+                        .required(true)
+                        .help("Nick of a followed user, or your own."),
+                ),
+        )
         .get_matches();
 
     println!();
@@ -55,6 +75,12 @@ fn main() {
         ("tweet", _args) => {
             timeline::tweet();
         }
+        ("thread", Some(args)) => {
+            timeline::thread(args.value_of("hash").unwrap_or_default());
+        }
+        ("history", Some(args)) => {
+            timeline::history(args.value_of("nick").unwrap_or_default());
+        }
         ("timeline", _args) => {
             timeline::show();
         }
@@ -1,27 +1,28 @@
 use chrono::prelude::*;
 use colored::*;
-use rustwtxt::{TweetMap, Twtxt};
+use rustwtxt::{Conversation, Mention, Subject, Token, Tweet, Twtxt};
 
 use std::collections::BTreeMap;
 use std::fs;
+use std::io::{BufReader, Write};
 use std::process;
 
 use crate::cache;
 use crate::conf;
 use crate::ed;
 
-#[derive(Debug, Clone)]
-struct Timeline {
-    tweets: TweetMap,
-}
+// How many archives deep `history` will follow a feed's `prev` links.
+const HISTORY_DEPTH: usize = 100;
+
+// Rendered tweets keyed by when they were posted, then the URL of the feed
+// and their position in it, so tweets from the same second are all kept.
+type Timeline = BTreeMap<(DateTime<FixedOffset>, String, usize), String>;
 
 pub fn tweet() {
     let twtxt_path = &*conf::DATA.path.clone();
     let tweet_body = ed::call();
 
-    let timestamp = Utc::now().to_rfc3339_opts(SecondsFormat::Secs, false);
-
-    let tweet_body = format!("{}\t{}", timestamp, tweet_body);
+    let tweet = Tweet::new(Utc::now().into(), &tweet_body);
 
     let current_tweets = match fs::read_to_string(twtxt_path) {
         Ok(data) => data,
@@ -31,19 +32,19 @@ pub fn tweet() {
         }
     };
 
-    let mut line_vec = current_tweets.split('\n').collect::<Vec<&str>>();
-    let mut trimmed_line_vec = Vec::new();
-    line_vec.iter().for_each(|line| {
-        if line == &"" {
-            return;
-        }
-        trimmed_line_vec.push(line.to_owned());
-    });
+    // twtxt.txt is append-only, so the rest of the file is left untouched.
+    let mut new_tweet = tweet.to_string();
+    new_tweet.push('\n');
+    if !current_tweets.is_empty() && !current_tweets.ends_with('\n') {
+        new_tweet.insert(0, '\n');
+    }
 
-    line_vec.push(&tweet_body);
-    let new_tweets = trimmed_line_vec.join("\n");
+    let appended = fs::OpenOptions::new()
+        .append(true)
+        .open(twtxt_path)
+        .and_then(|mut file| file.write_all(new_tweet.as_bytes()));
 
-    match fs::write(twtxt_path, new_tweets) {
+    match appended {
         Err(err) => {
             eprintln!("Couldn't append new tweet to twtxt.txt: {:?}", err);
         }
@@ -56,84 +57,195 @@ pub fn tweet() {
 }
 
 pub fn show() {
-    let twtxt_path = &*conf::DATA.path.clone();
-    let twtxt_str = match fs::read_to_string(twtxt_path) {
-        Ok(data) => data,
-        Err(_) => {
-            eprintln!("Couldn't read local twtxt.txt - omitting from timeline.");
-            "".into()
+    let mut timeline = pull_followed_tweets();
+    match own_feed() {
+        Ok(twtxt) => add_tweets(&mut timeline, conf::DATA.nick.green(), &twtxt),
+        Err(err) => {
+            eprintln!(
+                "Couldn't read local twtxt.txt - omitting from timeline: {}",
+                err
+            );
         }
-    };
+    }
+
+    timeline.values().for_each(|tweet| {
+        println!("{}", tweet);
+    });
+}
 
-    let nick = &*conf::DATA.nick;
-    let url = &*conf::DATA.url;
+pub fn thread(hash: &str) {
+    let urls = broken_follows()
+        .into_iter()
+        .map(|(_, url)| url)
+        .collect::<Vec<String>>();
+    let mut feeds = cache::twtxts(&urls)
+        .into_iter()
+        .filter_map(|(_, twtxt)| match twtxt {
+            Ok(data) => Some(data),
+            Err(err) => {
+                eprintln!("Skipping {}", err);
+                None
+            }
+        })
+        .collect::<Vec<Twtxt>>();
 
-    let tweet_lines = twtxt_str.split('\n').collect::<Vec<&str>>();
-    let mut tweet_lines_sanitized = Vec::new();
-    tweet_lines.iter().for_each(|line| {
-        if line == &"" || line.starts_with('#') {
-            return;
-        }
-        let timestamp = line.split('\t').collect::<Vec<&str>>();
-        if DateTime::parse_from_rfc3339(timestamp[0]).is_err() {
-            return;
+    if let Ok(twtxt) = own_feed() {
+        feeds.push(twtxt);
+    }
+
+    let conversation = match Conversation::find(&feeds, hash) {
+        Some(data) => data,
+        None => {
+            eprintln!("Couldn't find a conversation for {}", hash);
+            process::exit(1);
         }
+    };
+
+    if conversation.root().is_none() {
+        println!("The tweet that started this conversation isn't in any feed you follow.\n");
+    }
 
-        let line = format!(
-            "{}{}{}\n\t{}\n",
-            nick.green(),
+    conversation.posts().for_each(|post| {
+        println!(
+            "{}{}{}\n\t{}\t{}\t{}\n",
+            post.feed.nick().blue(),
             "@".bold(),
-            url.white(),
-            line.white().bold()
+            post.feed.url().white(),
+            post.hash().yellow(),
+            post.tweet.raw_timestamp().white().bold(),
+            render(post.tweet),
         );
-        let line = (timestamp[0].to_string(), line);
-        tweet_lines_sanitized.push(line);
     });
+}
 
-    let mut follows = pull_followed_tweets();
+pub fn history(nick: &str) {
+    let twtxt = if nick == conf::DATA.nick {
+        match own_feed() {
+            Ok(data) => data,
+            Err(err) => {
+                eprintln!("Can't read twtxt.txt: {}", err);
+                process::exit(1);
+            }
+        }
+    } else {
+        let url = match broken_follows().into_iter().find(|(each, _)| each == nick) {
+            Some((_, url)) => url,
+            None => {
+                eprintln!("You aren't following {}", nick);
+                process::exit(1);
+            }
+        };
+        match cache::twtxt(&url) {
+            Ok(data) => data,
+            Err(err) => {
+                eprintln!("{}", err);
+                process::exit(1);
+            }
+        }
+    };
 
-    tweet_lines_sanitized.iter().for_each(|(k, v)| {
-        follows.insert(k.to_owned(), v.to_owned());
-    });
+    let (history, error) = twtxt.fetch_history_with(cache::fetcher(), HISTORY_DEPTH);
+    if let Some(err) = error {
+        eprintln!("Couldn't fetch every archived tweet: {}", err);
+    }
 
-    follows.iter().for_each(|(_, v)| {
-        println!("{}", v);
+    history.tweets().values().for_each(|tweet| {
+        println!(
+            "{}{}{}\n\t{}\t{}\n",
+            history.nick().blue(),
+            "@".bold(),
+            history.url().white(),
+            tweet.raw_timestamp().white().bold(),
+            render(tweet),
+        );
     });
 }
 
-fn pull_followed_tweets() -> BTreeMap<String, String> {
+fn render(tweet: &Tweet) -> String {
+    tweet
+        .tokens()
+        .into_iter()
+        .map(|token| match token {
+            Token::Text(text) => text.white().bold().to_string(),
+            Token::Mention(mention) => format!("@{}", mention_name(&mention)).blue().to_string(),
+            Token::Tag(tag) => tag.cyan().to_string(),
+            Token::Link { text, url } if text == url => url.underline().to_string(),
+            Token::Link { text, url } => format!("{} ({})", text.bold(), url.underline()),
+            Token::Image { alt, url } => format!("[{}] ({})", alt.italic(), url.underline()),
+            Token::Subject(Subject::Hash(hash)) => format!("(#{})", hash).yellow().to_string(),
+            Token::Subject(Subject::Mention(mention)) => format!("(@{})", mention_name(&mention))
+                .yellow()
+                .to_string(),
+            Token::Code(code) => code.green().to_string(),
+            Token::LineBreak => "\n\t".into(),
+        })
+        .collect()
+}
+
+fn mention_name(mention: &Mention) -> String {
+    match &mention.nick {
+        Some(nick) => nick.clone(),
+        None => mention.url.to_string(),
+    }
+}
+
+// Followed nicks and URLs, leaving out feeds that are dead.
+fn broken_follows() -> Vec<(String, String)> {
     let follows = &*conf::DATA.follow;
-    let broken_follows = follows
+    follows
         .iter()
         .map(|each| {
             let split = each.split(' ').collect::<Vec<&str>>();
             (split[0].into(), split[1].into())
         })
-        .collect::<Vec<(String, String)>>();
+        .filter(|(_, url)| !conf::DATA.dead.contains(url))
+        .collect::<Vec<(String, String)>>()
+}
 
-    let mut tweetmap = BTreeMap::new();
+// Our own feed is read from disk but named by the URL it's published at,
+// so hashes and mentions match what followers see. It needn't declare
+// our nick.
+fn own_feed() -> rustwtxt::Result<Twtxt> {
+    let file = fs::File::open(&*conf::DATA.path)?;
+    Twtxt::from_reader_with_nick(BufReader::new(file), &conf::DATA.url, &conf::DATA.nick)
+}
 
-    broken_follows.iter().for_each(|(nick, url)| {
-        let _modtime = cache::get_remote_modtime(url);
-        let twtxt = match Twtxt::from(url) {
-            Some(data) => data,
-            None => return,
-        };
-        let tweets = twtxt.tweets().clone();
-        tweets.iter().for_each(|(k, v)| {
-            tweetmap.insert(
-                k.clone(),
-                format!(
-                    "{}{}{}\n\t{}\t{}\n",
-                    nick.blue(),
-                    "@".bold(),
-                    url.white(),
-                    k.clone().white().bold(),
-                    (*v.body()).to_string().white().bold(),
-                ),
-            );
-        });
+fn add_tweets(timeline: &mut Timeline, nick: ColoredString, twtxt: &Twtxt) {
+    let url = twtxt.url();
+    twtxt.report().diagnostics().iter().for_each(|diagnostic| {
+        eprintln!("{}: {}", url, diagnostic);
+    });
+    twtxt.tweets().iter().for_each(|((timestamp, seq), tweet)| {
+        timeline.insert(
+            (*timestamp, url.to_string(), *seq),
+            format!(
+                "{}{}{}\n\t{}\t{}\n",
+                nick,
+                "@".bold(),
+                url.white(),
+                tweet.raw_timestamp().white().bold(),
+                render(tweet),
+            ),
+        );
     });
+}
+
+fn pull_followed_tweets() -> Timeline {
+    let broken_follows = broken_follows();
+    let urls = broken_follows
+        .iter()
+        .map(|(_, url)| url.clone())
+        .collect::<Vec<String>>();
+    let feeds = cache::twtxts(&urls);
+
+    let mut timeline = BTreeMap::new();
+    broken_follows
+        .iter()
+        .zip(feeds)
+        .for_each(|((nick, _), (_, twtxt))| match twtxt {
+            Ok(twtxt) => add_tweets(&mut timeline, nick.blue(), &twtxt),
+            Err(err) => eprintln!("Skipping {}", err),
+        });
 
-    tweetmap
+    timeline
 }
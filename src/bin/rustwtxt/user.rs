@@ -1,84 +1,69 @@
-use std::fs;
-use std::process;
-
+use crate::cache;
 use crate::conf;
 
 pub fn follow(url: &str) {
-    let data = &*conf::DATA.follow;
-    let mut data = data.to_vec();
-    let twtxt = if let Ok(val) = rustwtxt::pull_twtxt(url) {
-        val
-    } else {
-        eprintln!("Can't pull twtxt file.");
-        eprintln!("I won't be able to parse the nick out of the metadata.");
-        String::new()
-    };
-
-    let nick = if let Ok(val) = rustwtxt::parse::metadata(&twtxt, "nick") {
-        val
-    } else {
-        eprintln!("Can't parse nick out of metadata.");
-        eprintln!("Please add it to the entry manually.");
-        String::new()
-    };
-
-    let entry = format!("{} {}", nick, url);
-    data.push(entry);
-
-    let nick = (&*conf::DATA.nick).to_owned();
-    let path = (&*conf::DATA.path).to_owned();
-    let url = (&*conf::DATA.url).to_owned();
-
-    let new_conf = conf::Data {
-        nick,
-        path,
-        url,
-        follow: data,
+    let nick = match cache::twtxt(url) {
+        Ok(twtxt) => twtxt.nick().to_string(),
+        Err(rustwtxt::Error::Http { status: 410, .. }) => {
+            eprintln!("Not following it.");
+            return;
+        }
+        Err(rustwtxt::Error::MissingMetadata(_)) => {
+            eprintln!("Can't parse nick out of metadata.");
+            eprintln!("Please add it to the entry manually.");
+            String::new()
+        }
+        Err(_) => {
+            eprintln!("Can't pull twtxt file.");
+            eprintln!("I won't be able to parse the nick out of the metadata.");
+            String::new()
+        }
     };
 
-    let yaml_str = if let Ok(yaml) = serde_yaml::to_string(&new_conf) {
-        yaml
-    } else {
-        eprintln!("Couldn't parse data as yaml.");
-        process::exit(1);
+    // Fetching the feed saves the config when it's gone or has moved, so
+    // it's read again rather than overwritten.
+    let mut data = conf::init();
+    let url = match cache::moved_to(url) {
+        Some(to) if data.rewrite_moved => to,
+        _ => url.to_string(),
     };
-
-    if let Err(err) = fs::write(&*conf::FILE, yaml_str) {
-        eprintln!("Couldn't rewrite config file: {:?}", err);
-    }
+    data.follow.push(format!("{} {}", nick, url));
+    conf::save(&data);
 }
 
 pub fn unfollow(nick: &str) {
-    let data = &*conf::DATA.follow;
-    let data = data.to_vec();
-    let mut new_data = vec![];
+    let mut data = conf::DATA.clone();
+    data.follow.retain(|entry| !entry.contains(nick));
+    conf::save(&data);
+}
 
-    data.iter().for_each(|entry| {
-        if entry.contains(nick) {
-            return;
+// Reports a followed feed that has moved for good, following it at its
+// new URL if the config asks for that. The config is read again, as
+// several feeds may move at once.
+pub fn moved(from: &str, to: &str) {
+    eprintln!("{} has moved to {}", from, to);
+    let mut data = conf::init();
+    if !data.rewrite_moved {
+        eprintln!("Set `rewrite_moved: true` in $HOME/.config/rustweet to follow it there.");
+        return;
+    }
+
+    data.follow.iter_mut().for_each(|entry| {
+        let split = entry.split(' ').collect::<Vec<&str>>();
+        if split.get(1) == Some(&from) {
+            *entry = format!("{} {}", split[0], to);
         }
-        new_data.push(entry.to_owned());
     });
+    conf::save(&data);
+}
 
-    let nick = (&*conf::DATA.nick).to_owned();
-    let path = (&*conf::DATA.path).to_owned();
-    let url = (&*conf::DATA.url).to_owned();
-
-    let new_conf = conf::Data {
-        nick,
-        path,
-        url,
-        follow: new_data,
-    };
-
-    let yaml_str = if let Ok(yaml) = serde_yaml::to_string(&new_conf) {
-        yaml
-    } else {
-        eprintln!("Couldn't reparse data as yaml.");
-        process::exit(1);
-    };
-
-    if let Err(err) = fs::write(&*conf::FILE, yaml_str) {
-        eprintln!("Couldn't rewrite config file: {:?}", err);
+// Marks a followed feed that is gone for good as dead, so it isn't
+// fetched again.
+pub fn gone(url: &str) {
+    eprintln!("{} is gone, and won't be fetched again.", url);
+    let mut data = conf::init();
+    if !data.dead.iter().any(|dead| dead == url) {
+        data.dead.push(url.into());
+        conf::save(&data);
     }
 }
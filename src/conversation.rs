@@ -0,0 +1,268 @@
+//! Reply threading, following the twt subject extension used by
+//! `yarn.social` and `jenny`: a reply starts with `(#hash)`, naming
+//! the twt hash of the tweet that started the conversation.
+
+use std::collections::BTreeMap;
+
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use crate::{Mention, Tweet, Twtxt};
+
+lazy_static! {
+    static ref SUBJECT_REGEX: Regex = Regex::new(
+        r"^\s*\((?:#([a-z0-9]+)|#<([a-z0-9]+)(?:\s+[^\s<>]+)?>|(@<[^\s<>]+(?:\s+[^\s<>]+)?>))\)"
+    )
+    .unwrap();
+}
+
+/// The subject a tweet is written under, taken from the start of its body.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Subject {
+    /// `(#abcdefg)` or `(#<abcdefg https://example.com/twt/abcdefg>)`:
+    /// a reply to the tweet with the given twt hash.
+    Hash(String),
+    /// `(@<nick url>)`: a tweet addressed to another feed.
+    Mention(Mention),
+}
+
+impl Subject {
+    /// Parses the subject from the start of a tweet body, if there is one.
+    ///
+    /// # Examples
+    /// ```
+    /// # use rustwtxt::Subject;
+    /// let subject = Subject::parse("(#abcdefg) I agree!");
+    /// assert_eq!(Some(Subject::Hash("abcdefg".into())), subject);
+    /// assert_eq!(None, Subject::parse("I agree! (#abcdefg)"));
+    /// ```
+    pub fn parse(body: &str) -> Option<Subject> {
+        Subject::parse_with_end(body).map(|(subject, _)| subject)
+    }
+
+    // Also returns where the subject, including its parentheses, ends.
+    pub(crate) fn parse_with_end(body: &str) -> Option<(Subject, usize)> {
+        let caps = SUBJECT_REGEX.captures(body)?;
+        let end = caps.get(0)?.end();
+        if let Some(hash) = caps.get(1).or_else(|| caps.get(2)) {
+            return Some((Subject::Hash(hash.as_str().to_string()), end));
+        }
+
+        let mention = caps.get(3)?;
+        let found = Mention::at_start(mention.as_str(), mention.start())?;
+        Some((Subject::Mention(found), end))
+    }
+
+    /// The twt hash this subject replies to, if any.
+    pub fn hash(&self) -> Option<&str> {
+        match self {
+            Subject::Hash(hash) => Some(hash),
+            Subject::Mention(_) => None,
+        }
+    }
+}
+
+/// A tweet taking part in a conversation, along with the feed it came from.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Post<'a> {
+    pub feed: &'a Twtxt,
+    pub tweet: &'a Tweet,
+}
+
+impl<'a> Post<'a> {
+    /// The twt hash of the tweet.
+    pub fn hash(&self) -> String {
+        self.feed.tweet_hash(self.tweet)
+    }
+}
+
+/// A conversation assembled from several feeds: the tweet that started
+/// it and every reply to it, oldest first.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Conversation<'a> {
+    hash: String,
+    root: Option<Post<'a>>,
+    replies: Vec<Post<'a>>,
+}
+
+impl<'a> Conversation<'a> {
+    /// Assembles the conversation containing the tweet with the given hash.
+    /// The hash may be that of the root or of any reply. Returns `None` if
+    /// no tweet in the feeds has, or replies to, that hash.
+    pub fn find(feeds: &'a [Twtxt], hash: &str) -> Option<Conversation<'a>> {
+        let posts = posts(feeds);
+        let root_hash = posts
+            .iter()
+            .find(|(post_hash, _)| post_hash == hash)
+            .and_then(|(_, post)| post.tweet.subject())
+            .and_then(Subject::hash)
+            .unwrap_or(hash)
+            .to_string();
+
+        let conversation = build(&posts, root_hash);
+        if conversation.root.is_none() && conversation.replies.is_empty() {
+            return None;
+        }
+        Some(conversation)
+    }
+
+    /// Assembles every conversation with at least one reply, ordered by
+    /// the time of their first tweet.
+    pub fn all(feeds: &'a [Twtxt]) -> Vec<Conversation<'a>> {
+        let posts = posts(feeds);
+        let mut root_hashes = Vec::new();
+        posts.iter().for_each(|(_, post)| {
+            if let Some(hash) = post.tweet.subject().and_then(Subject::hash) {
+                if !root_hashes.iter().any(|seen| seen == hash) {
+                    root_hashes.push(hash.to_string());
+                }
+            }
+        });
+
+        let mut conversations = root_hashes
+            .into_iter()
+            .map(|hash| build(&posts, hash))
+            .collect::<Vec<Conversation>>();
+        conversations.sort_by_key(|conversation| {
+            conversation
+                .posts()
+                .next()
+                .map(|post| post.tweet.timestamp())
+        });
+        conversations
+    }
+
+    /// The twt hash of the tweet that started the conversation.
+    pub fn hash(&self) -> &str {
+        &self.hash
+    }
+
+    /// The tweet that started the conversation. This is `None` when
+    /// the root is in a feed that wasn't provided.
+    pub fn root(&self) -> Option<&Post<'a>> {
+        self.root.as_ref()
+    }
+
+    /// Replies to the root, oldest first.
+    pub fn replies(&self) -> &[Post<'a>] {
+        &self.replies
+    }
+
+    /// The root, if known, followed by the replies.
+    pub fn posts(&self) -> impl Iterator<Item = &Post<'a>> {
+        self.root.iter().chain(self.replies.iter())
+    }
+}
+
+// Every tweet in the feeds with its hash, oldest first.
+fn posts(feeds: &[Twtxt]) -> Vec<(String, Post<'_>)> {
+    let mut posts = BTreeMap::new();
+    feeds.iter().for_each(|feed| {
+        feed.tweets().iter().for_each(|((timestamp, seq), tweet)| {
+            let post = Post { feed, tweet };
+            posts.insert((*timestamp, feed.url(), *seq), (post.hash(), post));
+        });
+    });
+    posts.into_values().collect()
+}
+
+fn build<'a>(posts: &[(String, Post<'a>)], hash: String) -> Conversation<'a> {
+    let root = posts
+        .iter()
+        .find(|(post_hash, _)| *post_hash == hash)
+        .map(|(_, post)| *post);
+    let replies = posts
+        .iter()
+        .filter(|(_, post)| post.tweet.subject().and_then(Subject::hash) == Some(&hash))
+        .map(|(_, post)| *post)
+        .collect();
+
+    Conversation {
+        hash,
+        root,
+        replies,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALICE: &str = "# nick = alice
+# url = https://alice.example.com/twtxt.txt
+2020-01-01T10:00:00Z\tWhat's everyone reading?
+2020-01-01T12:00:00Z\tSomething unrelated
+";
+
+    const BOB: &str = "# nick = bob
+# url = https://bob.example.com/twtxt.txt
+2020-01-01T11:30:00+01:00\t(#{root}) Dune, again.
+";
+
+    const CAROL: &str = "# nick = carol
+2020-01-01T10:45:00Z\t(#<{root} https://pod.example.com/twt/{root}>) Nothing, sadly.
+2020-01-01T13:00:00Z\t(@<bob https://bob.example.com/twtxt.txt>) good choice
+";
+
+    fn feeds() -> (Vec<Twtxt>, String) {
+        let alice = Twtxt::parse(ALICE, "https://alice.example.com/twtxt.txt").unwrap();
+        let root = alice.tweet("2020-01-01T10:00:00Z").unwrap();
+        let root = alice.tweet_hash(root);
+        let bob = Twtxt::parse(
+            &BOB.replace("{root}", &root),
+            "https://bob.example.com/twtxt.txt",
+        )
+        .unwrap();
+        let carol = Twtxt::parse(
+            &CAROL.replace("{root}", &root),
+            "https://carol.example.com/twtxt.txt",
+        )
+        .unwrap();
+        (vec![bob, alice, carol], root)
+    }
+
+    #[test]
+    fn parse_subjects() {
+        assert_eq!(
+            Some(Subject::Hash("abcdefg".into())),
+            Subject::parse("(#<abcdefg https://pod.example.com/twt/abcdefg>) hi")
+        );
+        match Subject::parse("(@<bob https://bob.example.com/twtxt.txt>) hi") {
+            Some(Subject::Mention(mention)) => {
+                assert_eq!(Some("bob".to_string()), mention.nick);
+                assert_eq!(1..41, mention.span);
+            }
+            other => panic!("unexpected subject: {:?}", other),
+        }
+        assert_eq!(None, Subject::parse("(just parentheses) hi"));
+        assert_eq!(None, Subject::parse("#abcdefg hi"));
+    }
+
+    #[test]
+    fn find_conversation() {
+        let (feeds, root) = feeds();
+        let conversation = Conversation::find(&feeds, &root).unwrap();
+        assert_eq!(root, conversation.hash());
+        assert_eq!("alice", conversation.root().unwrap().feed.nick());
+
+        let replies = conversation
+            .replies()
+            .iter()
+            .map(|post| post.feed.nick())
+            .collect::<Vec<&str>>();
+        assert_eq!(vec!["bob", "carol"], replies);
+
+        let reply = conversation.replies()[1].hash();
+        assert_eq!(conversation, Conversation::find(&feeds, &reply).unwrap());
+        assert!(Conversation::find(&feeds, "aaaaaaa").is_none());
+    }
+
+    #[test]
+    fn all_conversations() {
+        let (feeds, root) = feeds();
+        let conversations = Conversation::all(&feeds);
+        assert_eq!(1, conversations.len());
+        assert_eq!(root, conversations[0].hash());
+        assert_eq!(3, conversations[0].posts().count());
+    }
+}
@@ -0,0 +1,112 @@
+//! The error type used throughout the library.
+
+use std::fmt;
+use std::io;
+
+use crate::fetch::Limit;
+use crate::report::Problem;
+
+/// Shorthand for results carrying a `rustwtxt::Error`.
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Everything that can go wrong while fetching or parsing a `twtxt.txt` file.
+#[derive(Debug)]
+pub enum Error {
+    /// The server answered with an error status, such as `404`.
+    Http { url: String, status: u16 },
+    /// The request failed before the server answered: a bad URL,
+    /// a failed DNS lookup, a refused connection and so on.
+    Transport { url: String, message: String },
+    /// The server took too long to answer.
+    Timeout { url: String },
+    /// The file isn't valid UTF-8.
+    Encoding { url: String },
+    /// The file broke one of the limits in `FetchOptions`, so it was
+    /// given up on.
+    Limit { url: String, limit: Limit },
+    /// A line couldn't be parsed. Lines are numbered from 1.
+    MalformedLine {
+        line: usize,
+        content: String,
+        problem: Problem,
+    },
+    /// A timestamp isn't valid `RFC 3339`.
+    Timestamp(String),
+    /// The file doesn't declare a required metadata key, such as `nick`.
+    MissingMetadata(String),
+    /// Reading a local file failed.
+    Io(io::Error),
+}
+
+impl Error {
+    // Sorts out the errors ureq reports as synthetic responses.
+    pub(crate) fn from_ureq(url: &str, err: &ureq::Error) -> Error {
+        match err {
+            ureq::Error::Io(err) => Error::from_io(url, err),
+            _ => Error::Transport {
+                url: url.into(),
+                message: err.body_text(),
+            },
+        }
+    }
+
+    // Errors raised while talking to a remote host. TLS failures arrive
+    // wrapped as invalid data, and mustn't be mistaken for bad encoding.
+    pub(crate) fn from_io(url: &str, err: &io::Error) -> Error {
+        let tls = err
+            .get_ref()
+            .and_then(|inner| inner.downcast_ref::<rustls::TLSError>());
+        if let Some(tls) = tls {
+            return Error::Transport {
+                url: url.into(),
+                message: tls.to_string(),
+            };
+        }
+
+        match err.kind() {
+            io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => {
+                Error::Timeout { url: url.into() }
+            }
+            io::ErrorKind::InvalidData => Error::Encoding { url: url.into() },
+            _ => Error::Transport {
+                url: url.into(),
+                message: err.to_string(),
+            },
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Http { url, status } => write!(f, "{} :: HTTP {}", url, status),
+            Error::Transport { url, message } => write!(f, "{} :: {}", url, message),
+            Error::Timeout { url } => write!(f, "{} :: Timed out", url),
+            Error::Encoding { url } => write!(f, "{} :: Not valid UTF-8", url),
+            Error::Limit { url, limit } => write!(f, "{} :: {} limit exceeded", url, limit),
+            Error::MalformedLine {
+                line,
+                content,
+                problem,
+            } => write!(f, "Malformed line {}: {}: {:?}", line, problem, content),
+            Error::Timestamp(raw) => write!(f, "Invalid timestamp: {:?}", raw),
+            Error::MissingMetadata(key) => write!(f, "Missing metadata: {}", key),
+            Error::Io(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        Error::Io(err)
+    }
+}
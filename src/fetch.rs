@@ -0,0 +1,1315 @@
+//! Downloading remote `twtxt.txt` files, reusing a cached copy when
+//! the server reports that it hasn't changed and downloading only what
+//! was appended when it has.
+//!
+//! Downloads go through a `Fetcher`. The default `Registry` picks one by
+//! the scheme of the URL, and other transports can be registered with it.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::io::{self, Read};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::{Error, Result};
+
+pub mod file;
+pub mod gemini;
+pub mod gopher;
+
+pub use file::FileFetcher;
+pub use gemini::GeminiFetcher;
+pub use gopher::GopherFetcher;
+
+/// A `twtxt.txt` file as it was downloaded, along with the validators
+/// the server sent for it. Keep it around and pass it back when fetching
+/// again to avoid downloading an unchanged file.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Fetched {
+    pub body: String,
+    /// The `ETag` header.
+    pub etag: Option<String>,
+    /// The `Last-Modified` header.
+    pub last_modified: Option<String>,
+    /// The redirects followed to reach the file, in order.
+    pub redirects: Vec<Redirect>,
+}
+
+impl Fetched {
+    /// The URL the file was finally downloaded from, if a redirect was
+    /// followed to get there.
+    pub fn final_url(&self) -> Option<&str> {
+        self.redirects.last().map(|redirect| redirect.to.as_str())
+    }
+
+    /// Where the feed now lives, if every redirect followed to reach it
+    /// was permanent. The feed should be followed there from now on.
+    pub fn moved_to(&self) -> Option<&str> {
+        if self.redirects.iter().all(Redirect::is_permanent) {
+            self.final_url()
+        } else {
+            None
+        }
+    }
+}
+
+/// A redirect followed while fetching.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Redirect {
+    /// The status the server answered with, such as `301`, or `31` for
+    /// a permanent Gemini redirect.
+    pub status: u16,
+    /// The URL that was requested.
+    pub from: String,
+    /// The URL the server pointed to.
+    pub to: String,
+}
+
+impl Redirect {
+    /// Whether the server said the feed has moved for good.
+    pub fn is_permanent(&self) -> bool {
+        matches!(self.status, 301 | 308 | 31)
+    }
+}
+
+/// What came of fetching a `twtxt.txt` file.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum FetchOutcome {
+    /// The file was downloaded.
+    Modified(Fetched),
+    /// The server answered `304 Not Modified`: the cached copy is current.
+    NotModified,
+}
+
+/// Limits on what a single fetch may download, so that a hostile or
+/// broken feed can't exhaust memory or hang. Breaking one of them gives
+/// `Error::Limit`.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct FetchOptions {
+    /// The most bytes a file may have.
+    pub max_body_size: usize,
+    /// How long fetching the file may take, from making the request to
+    /// reading the last byte.
+    pub read_timeout: Duration,
+    /// The most bytes a single line may have.
+    pub max_line_length: usize,
+    /// How many of the newest tweets to keep from each feed. Fetchers
+    /// download the whole file, and `Twtxt::from_with()` drops the rest:
+    /// see `Twtxt::keep_newest()`.
+    pub max_tweets: usize,
+}
+
+impl Default for FetchOptions {
+    fn default() -> FetchOptions {
+        FetchOptions {
+            max_body_size: 8 * 1024 * 1024,
+            read_timeout: READ_TIMEOUT,
+            max_line_length: 64 * 1024,
+            max_tweets: 100_000,
+        }
+    }
+}
+
+/// Which of the `FetchOptions` limits a feed broke.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Limit {
+    /// The file was larger than `max_body_size`.
+    BodySize,
+    /// Reading the file took longer than `read_timeout`.
+    ReadTimeout,
+    /// A line was longer than `max_line_length`.
+    LineLength,
+}
+
+impl std::fmt::Display for Limit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let limit = match self {
+            Limit::BodySize => "Body size",
+            Limit::ReadTimeout => "Read timeout",
+            Limit::LineLength => "Line length",
+        };
+        write!(f, "{}", limit)
+    }
+}
+
+/// Something that can download `twtxt.txt` files, such as over HTTP.
+pub trait Fetcher: Send + Sync {
+    /// Downloads the file at `url`. When a cached copy is given and the
+    /// file hasn't changed since, `FetchOutcome::NotModified` may be
+    /// returned instead.
+    fn fetch(&self, url: &str, cached: Option<&Fetched>) -> Result<FetchOutcome>;
+
+    /// The limits `url` is held to when fetched.
+    fn options(&self, _url: &str) -> FetchOptions {
+        FetchOptions::default()
+    }
+}
+
+/// Who is fetching, sent in the `User-Agent` header as the twtxt spec asks
+/// so that authors can find their followers.
+///
+/// # Examples
+/// ```
+/// # use rustwtxt::fetch::ClientIdentity;
+/// let identity = ClientIdentity::new("foo", "https://example.com/twtxt.txt");
+/// assert!(identity
+///     .user_agent()
+///     .ends_with(" (+https://example.com/twtxt.txt; @foo)"));
+/// ```
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ClientIdentity {
+    /// The follower's nick.
+    pub nick: String,
+    /// The URL of the follower's own feed.
+    pub url: String,
+    /// How many followers an aggregator is fetching for. When set, the
+    /// nick and URL are the aggregator's.
+    pub followers: Option<usize>,
+}
+
+impl ClientIdentity {
+    /// Identifies a single follower.
+    pub fn new(nick: &str, url: &str) -> ClientIdentity {
+        ClientIdentity {
+            nick: nick.into(),
+            url: url.into(),
+            followers: None,
+        }
+    }
+
+    /// Identifies an aggregator fetching on behalf of `count` followers.
+    pub fn with_followers(mut self, count: usize) -> ClientIdentity {
+        self.followers = Some(count);
+        self
+    }
+
+    /// `twtxt/<version> (+<url>; @<nick>)`, or
+    /// `twtxt/<version> (~<count> followers; +<url>; @<nick>)` for
+    /// an aggregator.
+    pub fn user_agent(&self) -> String {
+        match self.followers {
+            Some(count) => format!(
+                "{} (~{} followers; +{}; @{})",
+                USER_AGENT, count, self.url, self.nick
+            ),
+            None => format!("{} (+{}; @{})", USER_AGENT, self.url, self.nick),
+        }
+    }
+}
+
+/// Fetches over HTTP and HTTPS. See `fetch()`.
+#[derive(Debug, Clone, Default)]
+pub struct HttpFetcher {
+    identity: Option<ClientIdentity>,
+    options: FetchOptions,
+}
+
+impl HttpFetcher {
+    /// Creates a fetcher that doesn't say who is following.
+    pub fn new() -> HttpFetcher {
+        HttpFetcher::default()
+    }
+
+    /// Creates a fetcher that names the follower in its `User-Agent`.
+    pub fn with_identity(identity: ClientIdentity) -> HttpFetcher {
+        HttpFetcher {
+            identity: Some(identity),
+            ..HttpFetcher::default()
+        }
+    }
+
+    /// Limits what each fetch may download.
+    pub fn with_options(mut self, options: FetchOptions) -> HttpFetcher {
+        self.options = options;
+        self
+    }
+}
+
+impl Fetcher for HttpFetcher {
+    fn fetch(&self, url: &str, cached: Option<&Fetched>) -> Result<FetchOutcome> {
+        fetch_as(url, cached, self)
+    }
+
+    fn options(&self, _url: &str) -> FetchOptions {
+        self.options
+    }
+}
+
+/// Picks a `Fetcher` by the scheme of the URL. `Registry::default()`
+/// handles `http`, `https`, `gopher`, `gemini` and `file`, remembering
+/// Gemini certificates only in memory. Plain paths are read as `file`
+/// URLs.
+///
+/// # Examples
+/// ```
+/// # use rustwtxt::fetch::{MemoryFetcher, Registry};
+/// # use rustwtxt::Twtxt;
+/// let mut files = MemoryFetcher::new();
+/// files.insert("mem://foo/twtxt.txt", "# nick = foo\n");
+///
+/// let mut registry = Registry::default();
+/// registry.register("mem", files);
+/// let twtxt = Twtxt::from_with(&registry, "mem://foo/twtxt.txt").unwrap();
+/// assert_eq!("foo", twtxt.nick());
+/// ```
+pub struct Registry {
+    fetchers: BTreeMap<String, Box<dyn Fetcher>>,
+}
+
+impl Registry {
+    /// Creates a registry without any fetchers.
+    pub fn new() -> Registry {
+        Registry {
+            fetchers: BTreeMap::new(),
+        }
+    }
+
+    /// Uses `fetcher` for URLs with the given scheme, replacing any
+    /// fetcher already registered for it.
+    pub fn register<F: Fetcher + 'static>(&mut self, scheme: &str, fetcher: F) -> &mut Registry {
+        self.fetchers
+            .insert(scheme.to_lowercase(), Box::new(fetcher));
+        self
+    }
+
+    /// The schemes fetchers are registered for.
+    pub fn schemes(&self) -> impl Iterator<Item = &str> {
+        self.fetchers.keys().map(String::as_str)
+    }
+}
+
+impl Default for Registry {
+    fn default() -> Registry {
+        let mut registry = Registry::new();
+        registry.register("http", HttpFetcher::new());
+        registry.register("https", HttpFetcher::new());
+        registry.register("gopher", GopherFetcher::new());
+        registry.register("gemini", GeminiFetcher::new());
+        registry.register("file", FileFetcher::new());
+        registry
+    }
+}
+
+impl std::fmt::Debug for Registry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Registry")
+            .field("schemes", &self.schemes().collect::<Vec<&str>>())
+            .finish()
+    }
+}
+
+impl Fetcher for Registry {
+    fn fetch(&self, url: &str, cached: Option<&Fetched>) -> Result<FetchOutcome> {
+        let scheme = scheme(url);
+        match self.fetchers.get(&scheme) {
+            Some(fetcher) => fetcher.fetch(url, cached),
+            None => Err(Error::Transport {
+                url: url.into(),
+                message: format!("Unsupported scheme: {}", scheme),
+            }),
+        }
+    }
+
+    fn options(&self, url: &str) -> FetchOptions {
+        match self.fetchers.get(&scheme(url)) {
+            Some(fetcher) => fetcher.options(url),
+            None => FetchOptions::default(),
+        }
+    }
+}
+
+// Plain paths have no scheme, and are read as `file` URLs.
+fn scheme(url: &str) -> String {
+    match url.find("://") {
+        Some(idx) => url[..idx].to_lowercase(),
+        None => "file".into(),
+    }
+}
+
+/// Serves files from memory, for tests. URLs it doesn't have are
+/// answered with `Error::Http` and a status of `404`.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryFetcher {
+    files: BTreeMap<String, String>,
+}
+
+impl MemoryFetcher {
+    /// Creates a fetcher without any files.
+    pub fn new() -> MemoryFetcher {
+        MemoryFetcher::default()
+    }
+
+    /// Serves `body` at `url`.
+    pub fn insert(&mut self, url: &str, body: &str) -> &mut MemoryFetcher {
+        self.files.insert(url.into(), body.into());
+        self
+    }
+}
+
+impl Fetcher for MemoryFetcher {
+    fn fetch(&self, url: &str, cached: Option<&Fetched>) -> Result<FetchOutcome> {
+        let body = match self.files.get(url) {
+            Some(body) => body,
+            None => {
+                return Err(Error::Http {
+                    url: url.into(),
+                    status: 404,
+                })
+            }
+        };
+        if cached.is_some_and(|cached| &cached.body == body) {
+            return Ok(FetchOutcome::NotModified);
+        }
+        Ok(FetchOutcome::Modified(Fetched {
+            body: body.clone(),
+            ..Fetched::default()
+        }))
+    }
+}
+
+// How long to wait for a connection, and how long reading may take by
+// default once connected.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+// Opens a plain connection for the transports that ureq doesn't cover.
+pub(crate) fn connect(host: &str, port: u16) -> io::Result<TcpStream> {
+    let mut last_err = None;
+    for addr in (host, port).to_socket_addrs()? {
+        match TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT) {
+            Ok(stream) => {
+                stream.set_read_timeout(Some(READ_TIMEOUT))?;
+                return Ok(stream);
+            }
+            Err(err) => last_err = Some(err),
+        }
+    }
+    Err(last_err.unwrap_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No addresses found")))
+}
+
+// A connection on which every read and write waits only for what is left
+// of the read timeout, counted from `started`, rather than the whole of it.
+pub(crate) struct TimedStream {
+    stream: TcpStream,
+    read_timeout: Duration,
+    started: Instant,
+}
+
+impl TimedStream {
+    pub(crate) fn new(stream: TcpStream, options: &FetchOptions, started: Instant) -> TimedStream {
+        TimedStream {
+            stream,
+            read_timeout: options.read_timeout,
+            started,
+        }
+    }
+}
+
+impl Read for TimedStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let left = time_left(self.read_timeout, self.started)?;
+        self.stream.set_read_timeout(Some(left))?;
+        self.stream.read(buf)
+    }
+}
+
+impl io::Write for TimedStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let left = time_left(self.read_timeout, self.started)?;
+        self.stream.set_write_timeout(Some(left))?;
+        self.stream.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+// What is left of the read timeout, or an error once it's up.
+fn time_left(read_timeout: Duration, started: Instant) -> io::Result<Duration> {
+    match read_timeout.checked_sub(started.elapsed()) {
+        Some(left) if !left.is_zero() => Ok(left),
+        _ => Err(io::Error::new(io::ErrorKind::TimedOut, "Read timeout")),
+    }
+}
+
+const MAX_REDIRECTS: usize = 5;
+const USER_AGENT: &str = concat!("twtxt/", env!("CARGO_PKG_VERSION"));
+
+// How many bytes of the cached copy are requested again when fetching
+// only what was appended, to check the end of the file wasn't edited.
+const OVERLAP: usize = 256;
+
+/// Downloads a `twtxt.txt` file over HTTP. When a cached copy is given, its
+/// validators are sent as `If-None-Match` and `If-Modified-Since`, and
+/// `FetchOutcome::NotModified` is returned if the file hasn't changed.
+///
+/// As `twtxt.txt` files are appended to, only the bytes after the cached
+/// copy are requested, along with its last 256 bytes. If the server doesn't
+/// support ranges, or those bytes no longer match because the author edited
+/// the end of the file, the whole file is downloaded instead. Edits further
+/// back aren't noticed: fetch without the cached copy to download the whole
+/// file again.
+///
+/// Redirects are followed and listed in `Fetched::redirects`, so a feed
+/// that has moved for good can be followed at its new URL. The cached
+/// validators are sent to the URL the cached copy was redirected to. A
+/// feed that is gone answers with `Error::Http` and a status of `410`.
+///
+/// # Examples
+/// ```no_run
+/// # use rustwtxt::fetch::{self, FetchOutcome};
+/// let url = "https://example.com/twtxt.txt";
+/// let cached = match fetch::fetch(url, None).unwrap() {
+///     FetchOutcome::Modified(fetched) => fetched,
+///     FetchOutcome::NotModified => unreachable!(),
+/// };
+///
+/// let body = match fetch::fetch(url, Some(&cached)).unwrap() {
+///     FetchOutcome::Modified(fetched) => fetched.body,
+///     FetchOutcome::NotModified => cached.body,
+/// };
+/// ```
+pub fn fetch(url: &str, cached: Option<&Fetched>) -> Result<FetchOutcome> {
+    fetch_as(url, cached, &HttpFetcher::new())
+}
+
+fn fetch_as(url: &str, cached: Option<&Fetched>, fetcher: &HttpFetcher) -> Result<FetchOutcome> {
+    let options = &fetcher.options;
+    let mut current = url.to_string();
+    let mut redirects = Vec::new();
+    // Validators only hold for the URL that sent them, which is wherever
+    // the cached copy was redirected to.
+    let source = cached.and_then(Fetched::final_url).unwrap_or(url);
+    // One deadline covers every request, redirects and all.
+    let started = Instant::now();
+    let outcome = loop {
+        let cached = cached.filter(|_| current == source);
+        let appending = cached.filter(|cached| !cached.body.is_empty());
+        let mut req = request(&current, cached, fetcher, started);
+        if let Some(cached) = appending {
+            req.set("Range", &format!("bytes={}-", range_start(cached)));
+        }
+
+        let resp = call(&current, req, options, started)?;
+        let to = match (location(&current, &resp)?, appending) {
+            (Some(to), _) => to,
+            (None, None) => break outcome(&current, resp, cached.is_some(), options, started)?,
+            (None, Some(cached)) => match appended(&current, cached, resp, options, started)? {
+                Some(outcome) => break outcome,
+                None => {
+                    let req = request(&current, Some(cached), fetcher, started);
+                    let resp = call(&current, req, options, started)?;
+                    break outcome(&current, resp, true, options, started)?;
+                }
+            },
+        };
+
+        if redirects.len() == MAX_REDIRECTS {
+            return Err(Error::Transport {
+                url: url.into(),
+                message: "Too many redirects".into(),
+            });
+        }
+        redirects.push(Redirect {
+            status: resp.status(),
+            from: current,
+            to: to.clone(),
+        });
+        current = to;
+    };
+
+    Ok(match outcome {
+        FetchOutcome::Modified(fetched) => FetchOutcome::Modified(Fetched {
+            redirects,
+            ..fetched
+        }),
+        FetchOutcome::NotModified => FetchOutcome::NotModified,
+    })
+}
+
+// Where a redirect points, resolved against the URL that was requested.
+fn location(url: &str, resp: &ureq::Response) -> Result<Option<String>> {
+    if !matches!(resp.status(), 301 | 302 | 303 | 307 | 308) {
+        return Ok(None);
+    }
+    let location = match resp.header("Location") {
+        Some(location) => location,
+        None => return Ok(None),
+    };
+    url::Url::parse(url)
+        .and_then(|base| base.join(location))
+        .map(|to| Some(to.to_string()))
+        .map_err(|err| Error::Transport {
+            url: url.into(),
+            message: format!("Bad redirect to {}: {}", location, err),
+        })
+}
+
+// What came of a request for the whole file.
+fn outcome(
+    url: &str,
+    resp: ureq::Response,
+    conditional: bool,
+    options: &FetchOptions,
+    started: Instant,
+) -> Result<FetchOutcome> {
+    if resp.status() == 304 && conditional {
+        return Ok(FetchOutcome::NotModified);
+    }
+    if resp.error() || resp.redirect() {
+        return Err(Error::Http {
+            url: url.into(),
+            status: resp.status(),
+        });
+    }
+
+    let (etag, last_modified) = validators(&resp);
+    let body = read_limited(url, body_reader(resp, options, started), options, started)?;
+    let body = String::from_utf8(body).map_err(|_| Error::Encoding { url: url.into() })?;
+
+    Ok(FetchOutcome::Modified(Fetched {
+        body,
+        etag,
+        last_modified,
+        ..Fetched::default()
+    }))
+}
+
+/// Fetches several `twtxt.txt` files at once, each with its cached copy if
+/// there is one, using at most `concurrency` threads. Results are returned
+/// in the order the feeds were given. Feeds still downloading once the
+/// deadline has passed are given up on with `Error::Timeout`, so a slow
+/// host can't hold up the rest.
+///
+/// # Examples
+/// ```no_run
+/// # use std::time::Duration;
+/// # use rustwtxt::fetch;
+/// let feeds = vec![
+///     ("https://example.com/twtxt.txt".to_string(), None),
+///     ("https://example.org/twtxt.txt".to_string(), None),
+/// ];
+/// for (url, outcome) in fetch::fetch_many(feeds, 4, Duration::from_secs(10)) {
+///     println!("{}: {:?}", url, outcome.is_ok());
+/// }
+/// ```
+pub fn fetch_many(
+    feeds: Vec<(String, Option<Fetched>)>,
+    concurrency: usize,
+    deadline: Duration,
+) -> Vec<(String, Result<FetchOutcome>)> {
+    fetch_many_with(Arc::new(Registry::default()), feeds, concurrency, deadline)
+}
+
+/// Fetches several `twtxt.txt` files at once with the given fetcher.
+/// See `fetch_many()`.
+pub fn fetch_many_with(
+    fetcher: Arc<dyn Fetcher>,
+    feeds: Vec<(String, Option<Fetched>)>,
+    concurrency: usize,
+    deadline: Duration,
+) -> Vec<(String, Result<FetchOutcome>)> {
+    let deadline = Instant::now() + deadline;
+    let urls = feeds
+        .iter()
+        .map(|(url, _)| url.clone())
+        .collect::<Vec<String>>();
+    let queue = Arc::new(Mutex::new(
+        feeds.into_iter().enumerate().collect::<VecDeque<_>>(),
+    ));
+
+    let (tx, rx) = mpsc::channel();
+    for _ in 0..concurrency.clamp(1, urls.len().max(1)) {
+        let queue = Arc::clone(&queue);
+        let fetcher = Arc::clone(&fetcher);
+        let tx = tx.clone();
+        thread::spawn(move || loop {
+            if Instant::now() >= deadline {
+                return;
+            }
+            let next = queue.lock().ok().and_then(|mut queue| queue.pop_front());
+            let (idx, (url, cached)) = match next {
+                Some(next) => next,
+                None => return,
+            };
+            if tx
+                .send((idx, fetcher.fetch(&url, cached.as_ref())))
+                .is_err()
+            {
+                return;
+            }
+        });
+    }
+    drop(tx);
+
+    let mut results = urls.iter().map(|_| None).collect::<Vec<_>>();
+    let mut pending = urls.len();
+    while pending > 0 {
+        let timeout = deadline.saturating_duration_since(Instant::now());
+        match rx.recv_timeout(timeout) {
+            Ok((idx, result)) => {
+                results[idx] = Some(result);
+                pending -= 1;
+            }
+            Err(_) => break,
+        }
+    }
+
+    urls.into_iter()
+        .zip(results)
+        .map(|(url, result)| {
+            let result = result.unwrap_or_else(|| Err(Error::Timeout { url: url.clone() }));
+            (url, result)
+        })
+        .collect()
+}
+
+// Where a request for what was appended since the cached copy starts.
+fn range_start(cached: &Fetched) -> usize {
+    cached.body.len().saturating_sub(OVERLAP)
+}
+
+// What came of a request for what was appended since the cached copy.
+// Returns `None` when the whole file has to be downloaded instead.
+fn appended(
+    url: &str,
+    cached: &Fetched,
+    resp: ureq::Response,
+    options: &FetchOptions,
+    started: Instant,
+) -> Result<Option<FetchOutcome>> {
+    let start = range_start(cached);
+    match resp.status() {
+        206 => {}
+        // The file shrank.
+        416 => return Ok(None),
+        // The server doesn't do ranges, or the file hasn't changed.
+        _ => return outcome(url, resp, true, options, started).map(Some),
+    }
+    if content_range_start(&resp) != Some(start) {
+        return Ok(None);
+    }
+
+    let (etag, last_modified) = validators(&resp);
+    let delta = read_limited(url, body_reader(resp, options, started), options, started)?;
+    let overlap = &cached.body.as_bytes()[start..];
+    if !delta.starts_with(overlap) {
+        return Ok(None);
+    }
+
+    let mut body = cached.body.clone().into_bytes();
+    body.extend_from_slice(&delta[overlap.len()..]);
+    if body.len() > options.max_body_size {
+        return Err(Error::Limit {
+            url: url.into(),
+            limit: Limit::BodySize,
+        });
+    }
+    let body = String::from_utf8(body).map_err(|_| Error::Encoding { url: url.into() })?;
+
+    Ok(Some(FetchOutcome::Modified(Fetched {
+        body,
+        etag,
+        last_modified,
+        ..Fetched::default()
+    })))
+}
+
+fn request(
+    url: &str,
+    cached: Option<&Fetched>,
+    fetcher: &HttpFetcher,
+    started: Instant,
+) -> ureq::Request {
+    // ureq waits this long for each read. The body is read through
+    // `body_reader()`, which holds the reads together to the read timeout,
+    // so this only ends the reads of a request that was given up on.
+    let left = fetcher
+        .options
+        .read_timeout
+        .saturating_sub(started.elapsed());
+    let mut req = ureq::get(url);
+    req.timeout_connect(CONNECT_TIMEOUT.as_millis() as u64)
+        .timeout_read((left.as_millis() as u64).max(1))
+        .redirects(0);
+    match &fetcher.identity {
+        Some(identity) => req.set("User-Agent", &identity.user_agent()),
+        None => req.set("User-Agent", USER_AGENT),
+    };
+    if let Some(cached) = cached {
+        if let Some(etag) = &cached.etag {
+            req.set("If-None-Match", etag);
+        }
+        if let Some(last_modified) = &cached.last_modified {
+            req.set("If-Modified-Since", last_modified);
+        }
+    }
+    req
+}
+
+// Makes the request on another thread, so a server trickling its headers
+// in can't hold us past the read timeout: ureq only times out each read.
+// A thread given up on is left to finish by itself.
+fn call(
+    url: &str,
+    mut req: ureq::Request,
+    options: &FetchOptions,
+    started: Instant,
+) -> Result<ureq::Response> {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let _ = tx.send(req.call());
+    });
+
+    let left = options.read_timeout.saturating_sub(started.elapsed());
+    let resp = match rx.recv_timeout(left) {
+        Ok(resp) => resp,
+        Err(mpsc::RecvTimeoutError::Timeout) => {
+            return Err(Error::Limit {
+                url: url.into(),
+                limit: Limit::ReadTimeout,
+            })
+        }
+        Err(mpsc::RecvTimeoutError::Disconnected) => {
+            return Err(Error::Transport {
+                url: url.into(),
+                message: "The request was abandoned".into(),
+            })
+        }
+    };
+    if let Some(err) = resp.synthetic_error() {
+        return Err(Error::from_ureq(url, err));
+    }
+    Ok(resp)
+}
+
+// Reads the body on another thread, as `call()` makes the request, so
+// that each read waits only for what is left of the read timeout.
+fn body_reader(resp: ureq::Response, options: &FetchOptions, started: Instant) -> Chunks {
+    let (tx, rx) = mpsc::sync_channel(1);
+    thread::spawn(move || {
+        let mut reader = resp.into_reader();
+        loop {
+            let mut chunk = vec![0; 8192];
+            let sent = match reader.read(&mut chunk) {
+                Ok(0) => return,
+                Ok(len) => {
+                    chunk.truncate(len);
+                    tx.send(Ok(chunk))
+                }
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => {
+                    let _ = tx.send(Err(err));
+                    return;
+                }
+            };
+            if sent.is_err() {
+                return;
+            }
+        }
+    });
+
+    Chunks {
+        rx,
+        chunk: Vec::new(),
+        read_timeout: options.read_timeout,
+        started,
+    }
+}
+
+// The body as it's read by `body_reader()`.
+struct Chunks {
+    rx: mpsc::Receiver<io::Result<Vec<u8>>>,
+    chunk: Vec<u8>,
+    read_timeout: Duration,
+    started: Instant,
+}
+
+impl Read for Chunks {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.chunk.is_empty() {
+            let left = time_left(self.read_timeout, self.started)?;
+            self.chunk = match self.rx.recv_timeout(left) {
+                Ok(chunk) => chunk?,
+                Err(mpsc::RecvTimeoutError::Timeout) => {
+                    return Err(io::Error::new(io::ErrorKind::TimedOut, "Read timeout"))
+                }
+                Err(mpsc::RecvTimeoutError::Disconnected) => return Ok(0),
+            };
+        }
+
+        let len = buf.len().min(self.chunk.len());
+        buf[..len].copy_from_slice(&self.chunk[..len]);
+        self.chunk.drain(..len);
+        Ok(len)
+    }
+}
+
+fn validators(resp: &ureq::Response) -> (Option<String>, Option<String>) {
+    (
+        resp.header("ETag").map(String::from),
+        resp.header("Last-Modified").map(String::from),
+    )
+}
+
+// The first byte of a `Content-Range: bytes start-end/total` header.
+fn content_range_start(resp: &ureq::Response) -> Option<usize> {
+    let range = resp
+        .header("Content-Range")?
+        .trim()
+        .strip_prefix("bytes ")?;
+    range.split('-').next()?.trim().parse().ok()
+}
+
+// Reads a body, giving up on it as soon as it breaks one of the limits.
+// The read timeout counts from `started`, when the request was made, so
+// a server that is slow to send its headers can't stretch it.
+pub(crate) fn read_limited<R: Read>(
+    url: &str,
+    mut reader: R,
+    options: &FetchOptions,
+    started: Instant,
+) -> Result<Vec<u8>> {
+    let exceeded = |limit| {
+        Err(Error::Limit {
+            url: url.into(),
+            limit,
+        })
+    };
+
+    let mut body = Vec::new();
+    let mut line_length = 0;
+    let mut buf = [0; 8192];
+    loop {
+        let len = match reader.read(&mut buf) {
+            Ok(0) => return Ok(body),
+            Ok(len) => len,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(io_error(url, &err, options, started)),
+        };
+
+        if body.len() + len > options.max_body_size {
+            return exceeded(Limit::BodySize);
+        }
+        for byte in &buf[..len] {
+            line_length = if *byte == b'\n' { 0 } else { line_length + 1 };
+            if line_length > options.max_line_length {
+                return exceeded(Limit::LineLength);
+            }
+        }
+        body.extend_from_slice(&buf[..len]);
+        if started.elapsed() > options.read_timeout {
+            return exceeded(Limit::ReadTimeout);
+        }
+    }
+}
+
+// An error while talking to a host, which breaks the read timeout if it
+// timed out once the time was up.
+pub(crate) fn io_error(
+    url: &str,
+    err: &io::Error,
+    options: &FetchOptions,
+    started: Instant,
+) -> Error {
+    let timed_out = matches!(
+        err.kind(),
+        io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock
+    );
+    if timed_out && started.elapsed() >= options.read_timeout {
+        return Error::Limit {
+            url: url.into(),
+            limit: Limit::ReadTimeout,
+        };
+    }
+    Error::from_io(url, err)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::{response, serve};
+    use std::io::Write;
+    use std::net::TcpListener;
+
+    #[test]
+    fn conditional_requests() {
+        let (url, requests) = serve(vec![
+            "HTTP/1.1 200 OK\r\nETag: \"v1\"\r\nLast-Modified: Wed, 01 Jan 2020 00:00:00 GMT\r\n\
+             Content-Length: 12\r\nConnection: close\r\n\r\n# nick = foo"
+                .into(),
+            "HTTP/1.1 304 Not Modified\r\nConnection: close\r\n\r\n".into(),
+        ]);
+
+        let fetched = match fetch(&url, None).unwrap() {
+            FetchOutcome::Modified(fetched) => fetched,
+            other => panic!("unexpected outcome: {:?}", other),
+        };
+        assert_eq!("# nick = foo", fetched.body);
+        assert_eq!(Some("\"v1\""), fetched.etag.as_deref());
+        assert!(!requests.recv().unwrap().contains("if-none-match"));
+
+        assert_eq!(
+            FetchOutcome::NotModified,
+            fetch(&url, Some(&fetched)).unwrap()
+        );
+        let request = requests.recv().unwrap();
+        assert!(request.contains("if-none-match: \"v1\""));
+        assert!(request.contains("if-modified-since: wed, 01 jan 2020 00:00:00 gmt"));
+    }
+
+    #[test]
+    fn user_agent() {
+        let ok = || response("200 OK", "", "# nick = bar");
+        let (url, requests) = serve(vec![ok(), ok(), ok()]);
+        let identity = ClientIdentity::new("foo", "https://example.com/twtxt.txt");
+
+        fetch(&url, None).unwrap();
+        let request = requests.recv().unwrap();
+        assert!(request.contains(&format!("user-agent: {}\r\n", USER_AGENT)));
+
+        HttpFetcher::with_identity(identity.clone())
+            .fetch(&url, None)
+            .unwrap();
+        let request = requests.recv().unwrap();
+        assert!(request.contains(" (+https://example.com/twtxt.txt; @foo)\r\n"));
+
+        HttpFetcher::with_identity(identity.with_followers(12))
+            .fetch(&url, None)
+            .unwrap();
+        let request = requests.recv().unwrap();
+        assert!(request.contains(" (~12 followers; +https://example.com/twtxt.txt; @foo)\r\n"));
+    }
+
+    fn cached() -> Fetched {
+        Fetched {
+            body: format!(
+                "# nick = foo\n{}",
+                "2020-01-01T00:00:00Z\tfiller\n".repeat(20)
+            ),
+            etag: Some("\"v1\"".into()),
+            ..Fetched::default()
+        }
+    }
+
+    #[test]
+    fn appended_tweets() {
+        let cached = cached();
+        let start = cached.body.len() - OVERLAP;
+        let delta = format!("{}2020-01-02T00:00:00Z\tnew\n", &cached.body[start..]);
+        let (url, requests) = serve(vec![response(
+            "206 Partial Content",
+            &format!(
+                "ETag: \"v2\"\r\nContent-Range: bytes {}-{}/{}\r\n",
+                start,
+                start + delta.len() - 1,
+                start + delta.len()
+            ),
+            &delta,
+        )]);
+
+        match fetch(&url, Some(&cached)).unwrap() {
+            FetchOutcome::Modified(fetched) => {
+                assert_eq!(
+                    format!("{}2020-01-02T00:00:00Z\tnew\n", cached.body),
+                    fetched.body
+                );
+                assert_eq!(Some("\"v2\""), fetched.etag.as_deref());
+            }
+            other => panic!("unexpected outcome: {:?}", other),
+        }
+        let request = requests.recv().unwrap();
+        assert!(request.contains(&format!("range: bytes={}-", start)));
+    }
+
+    #[test]
+    fn redirects() {
+        let (url, requests) = serve(vec![
+            response("301 Moved Permanently", "Location: /moved.txt\r\n", ""),
+            response("308 Permanent Redirect", "Location: /twtxt-new.txt\r\n", ""),
+            response("200 OK", "ETag: \"v2\"\r\n", "# nick = foo\n"),
+            response("302 Found", "Location: /mirror.txt\r\n", ""),
+            response("200 OK", "", "# nick = foo\n"),
+            response("410 Gone", "", ""),
+        ]);
+        let base = url.trim_end_matches("/twtxt.txt");
+
+        let fetched = match fetch(&url, Some(&cached())).unwrap() {
+            FetchOutcome::Modified(fetched) => fetched,
+            other => panic!("unexpected outcome: {:?}", other),
+        };
+        assert_eq!(2, fetched.redirects.len());
+        assert_eq!(301, fetched.redirects[0].status);
+        assert_eq!(format!("{}/moved.txt", base), fetched.redirects[0].to);
+        let moved_to = format!("{}/twtxt-new.txt", base);
+        assert_eq!(Some(moved_to.as_str()), fetched.moved_to());
+        assert!(requests.recv().unwrap().contains("if-none-match"));
+        let request = requests.recv().unwrap();
+        assert!(request.starts_with("get /moved.txt "));
+        assert!(!request.contains("if-none-match"));
+        assert!(requests.recv().unwrap().starts_with("get /twtxt-new.txt "));
+
+        let fetched = match fetch(&url, None).unwrap() {
+            FetchOutcome::Modified(fetched) => fetched,
+            other => panic!("unexpected outcome: {:?}", other),
+        };
+        assert_eq!(None, fetched.moved_to());
+        assert_eq!(
+            Some(format!("{}/mirror.txt", base).as_str()),
+            fetched.final_url()
+        );
+
+        match fetch(&url, None) {
+            Err(Error::Http { status: 410, .. }) => {}
+            other => panic!("unexpected outcome: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn redirected_validators() {
+        let (url, requests) = serve(vec![
+            response("301 Moved Permanently", "Location: /new.txt\r\n", ""),
+            "HTTP/1.1 304 Not Modified\r\nConnection: close\r\n\r\n".into(),
+        ]);
+        let moved_to = format!("{}/new.txt", url.trim_end_matches("/twtxt.txt"));
+        let cached = Fetched {
+            redirects: vec![Redirect {
+                status: 301,
+                from: url.clone(),
+                to: moved_to,
+            }],
+            ..cached()
+        };
+
+        assert_eq!(
+            FetchOutcome::NotModified,
+            fetch(&url, Some(&cached)).unwrap()
+        );
+        let request = requests.recv().unwrap();
+        assert!(!request.contains("if-none-match"));
+        assert!(!request.contains("range"));
+        let request = requests.recv().unwrap();
+        assert!(request.starts_with("get /new.txt "));
+        assert!(request.contains("if-none-match: \"v1\""));
+        assert!(request.contains("range: bytes="));
+    }
+
+    #[test]
+    fn limits() {
+        let options = FetchOptions {
+            max_body_size: 64,
+            read_timeout: Duration::from_millis(200),
+            max_line_length: 16,
+            ..FetchOptions::default()
+        };
+        let fetcher = HttpFetcher::new().with_options(options);
+        let (url, _) = serve(vec![
+            response("200 OK", "", &"# nick = foo\n".repeat(10)),
+            response("200 OK", "", &format!("# nick = {}\n", "x".repeat(20))),
+            response("200 OK", "", "# nick = foo\n"),
+        ]);
+        let limit = |result: Result<FetchOutcome>| match result {
+            Err(Error::Limit { limit, .. }) => limit,
+            other => panic!("unexpected outcome: {:?}", other),
+        };
+
+        assert_eq!(Limit::BodySize, limit(fetcher.fetch(&url, None)));
+        assert_eq!(Limit::LineLength, limit(fetcher.fetch(&url, None)));
+        assert!(fetcher.fetch(&url, None).is_ok());
+
+        // Sends half the body, then stalls.
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/twtxt.txt", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buf = [0; 1024];
+            let _ = stream.read(&mut buf);
+            let _ =
+                stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 26\r\n\r\n# nick = foo\n");
+            thread::sleep(Duration::from_secs(2));
+        });
+        assert_eq!(Limit::ReadTimeout, limit(fetcher.fetch(&url, None)));
+
+        // Sends a header line every 100ms, each well inside the read
+        // timeout, for far longer than the read timeout.
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/twtxt.txt", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buf = [0; 1024];
+            let _ = stream.read(&mut buf);
+            let _ = stream.write_all(b"HTTP/1.1 200 OK\r\n");
+            for _ in 0..30 {
+                thread::sleep(Duration::from_millis(100));
+                if stream.write_all(b"X-Slow: yes\r\n").is_err() {
+                    return;
+                }
+            }
+        });
+        let started = Instant::now();
+        assert_eq!(Limit::ReadTimeout, limit(fetcher.fetch(&url, None)));
+        assert!(started.elapsed() < Duration::from_millis(500));
+
+        // Trickles the headers in for most of the read timeout, then
+        // stalls in the body: the body only gets the time that's left.
+        let fetcher = HttpFetcher::new().with_options(FetchOptions {
+            read_timeout: Duration::from_millis(500),
+            ..options
+        });
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/twtxt.txt", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buf = [0; 1024];
+            let _ = stream.read(&mut buf);
+            let _ = stream.write_all(b"HTTP/1.1 200 OK\r\n");
+            for _ in 0..8 {
+                thread::sleep(Duration::from_millis(50));
+                let _ = stream.write_all(b"X-Slow: yes\r\n");
+            }
+            let _ = stream.write_all(b"Content-Length: 26\r\n\r\n# nick = foo\n");
+            thread::sleep(Duration::from_secs(2));
+        });
+        let started = Instant::now();
+        assert_eq!(Limit::ReadTimeout, limit(fetcher.fetch(&url, None)));
+        assert!(started.elapsed() < Duration::from_millis(750));
+    }
+
+    #[test]
+    fn tls_errors() {
+        // Presents a self-signed certificate, which isn't trusted.
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!(
+            "https://localhost:{}/twtxt.txt",
+            listener.local_addr().unwrap().port()
+        );
+        let cert = rcgen::generate_simple_self_signed(vec!["localhost".into()]).unwrap();
+        let mut config = rustls::ServerConfig::new(rustls::NoClientAuth::new());
+        config
+            .set_single_cert(
+                vec![rustls::Certificate(cert.serialize_der().unwrap())],
+                rustls::PrivateKey(cert.serialize_private_key_der()),
+            )
+            .unwrap();
+        let config = Arc::new(config);
+        std::thread::spawn(move || {
+            let (sock, _) = listener.accept().unwrap();
+            let session = rustls::ServerSession::new(&config);
+            let mut stream = rustls::StreamOwned::new(session, sock);
+            let mut buf = [0; 1024];
+            let _ = stream.read(&mut buf);
+        });
+
+        match fetch(&url, None) {
+            Err(Error::Transport { .. }) => {}
+            other => panic!("unexpected outcome: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn edited_history() {
+        let cached = cached();
+        let start = cached.body.len() - OVERLAP;
+        // The last tweet is edited, inside the bytes requested again.
+        let edited = format!("{}edited\n", cached.body.strip_suffix("filler\n").unwrap());
+        let (url, requests) = serve(vec![
+            response(
+                "206 Partial Content",
+                &format!(
+                    "Content-Range: bytes {}-{}/{}\r\n",
+                    start,
+                    edited.len() - 1,
+                    edited.len()
+                ),
+                &edited[start..],
+            ),
+            response("200 OK", "", &edited),
+            response("416 Range Not Satisfiable", "", ""),
+            response("200 OK", "", "# nick = foo\n"),
+        ]);
+
+        let fetched = match fetch(&url, Some(&cached)).unwrap() {
+            FetchOutcome::Modified(fetched) => fetched,
+            other => panic!("unexpected outcome: {:?}", other),
+        };
+        assert_eq!(edited, fetched.body);
+        assert!(requests.recv().unwrap().contains("range:"));
+        assert!(!requests.recv().unwrap().contains("range:"));
+
+        assert_eq!(
+            FetchOutcome::Modified(Fetched {
+                body: "# nick = foo\n".into(),
+                ..Fetched::default()
+            }),
+            fetch(&url, Some(&cached)).unwrap()
+        );
+    }
+
+    #[test]
+    fn many_feeds() {
+        let (fast, _) = serve(vec![response("200 OK", "", "# nick = fast\n")]);
+        let (missing, _) = serve(vec![response("404 Not Found", "", "")]);
+        // Accepts the connection but never answers.
+        let slow = TcpListener::bind("127.0.0.1:0").unwrap();
+        let slow_url = format!("http://{}/twtxt.txt", slow.local_addr().unwrap());
+        thread::spawn(move || {
+            let _conn = slow.accept();
+            thread::sleep(Duration::from_secs(30));
+        });
+
+        let started = Instant::now();
+        let results = fetch_many(
+            vec![
+                (slow_url.clone(), None),
+                (fast.clone(), None),
+                (missing.clone(), None),
+            ],
+            3,
+            Duration::from_secs(2),
+        );
+        assert!(started.elapsed() < Duration::from_secs(10));
+
+        let urls = results
+            .iter()
+            .map(|(url, _)| url.as_str())
+            .collect::<Vec<&str>>();
+        assert_eq!(vec![&*slow_url, &*fast, &*missing], urls);
+        assert!(matches!(results[0].1, Err(Error::Timeout { .. })));
+        assert!(matches!(results[1].1, Ok(FetchOutcome::Modified(_))));
+        assert!(matches!(results[2].1, Err(Error::Http { status: 404, .. })));
+    }
+
+    #[test]
+    fn registry() {
+        let mut files = MemoryFetcher::new();
+        files.insert("mem://foo/twtxt.txt", "# nick = foo\n");
+        let mut registry = Registry::new();
+        registry.register("MEM", files);
+        assert_eq!(vec!["mem"], registry.schemes().collect::<Vec<&str>>());
+
+        let fetched = match registry.fetch("mem://foo/twtxt.txt", None) {
+            Ok(FetchOutcome::Modified(fetched)) => fetched,
+            other => panic!("unexpected outcome: {:?}", other),
+        };
+        assert_eq!(
+            FetchOutcome::NotModified,
+            registry
+                .fetch("mem://foo/twtxt.txt", Some(&fetched))
+                .unwrap()
+        );
+        assert!(matches!(
+            registry.fetch("mem://bar/twtxt.txt", None),
+            Err(Error::Http { status: 404, .. })
+        ));
+        assert!(matches!(
+            registry.fetch("https://example.com/twtxt.txt", None),
+            Err(Error::Transport { .. })
+        ));
+        assert!(matches!(
+            registry.fetch("twtxt.txt", None),
+            Err(Error::Transport { .. })
+        ));
+    }
+}
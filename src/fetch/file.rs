@@ -0,0 +1,136 @@
+//! Reading `twtxt.txt` files from the local machine, named either by
+//! a `file://` URL or by a plain path.
+
+use std::fs::File;
+use std::path::PathBuf;
+use std::time::Instant;
+
+use super::{read_limited, FetchOptions, FetchOutcome, Fetched, Fetcher};
+use crate::{Error, Result};
+
+/// Reads local files. `FetchOutcome::NotModified` is returned when the
+/// file is the same as the cached copy.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FileFetcher {
+    options: FetchOptions,
+}
+
+impl FileFetcher {
+    /// Creates a fetcher with the default limits.
+    pub fn new() -> FileFetcher {
+        FileFetcher::default()
+    }
+
+    /// Limits what each fetch may read.
+    pub fn with_options(mut self, options: FetchOptions) -> FileFetcher {
+        self.options = options;
+        self
+    }
+}
+
+impl Fetcher for FileFetcher {
+    fn fetch(&self, url: &str, cached: Option<&Fetched>) -> Result<FetchOutcome> {
+        let started = Instant::now();
+        let file = File::open(path(url)?).map_err(|err| Error::from_io(url, &err))?;
+        let body = read_limited(url, file, &self.options, started)?;
+        let body = String::from_utf8(body).map_err(|_| Error::Encoding { url: url.into() })?;
+
+        match cached {
+            Some(cached) if cached.body == body => Ok(FetchOutcome::NotModified),
+            _ => Ok(FetchOutcome::Modified(Fetched {
+                body,
+                ..Fetched::default()
+            })),
+        }
+    }
+
+    fn options(&self, _url: &str) -> FetchOptions {
+        self.options
+    }
+}
+
+// `file:///srv/twtxt.txt` and `/srv/twtxt.txt` are the same file.
+fn path(url: &str) -> Result<PathBuf> {
+    if !is_file_url(url) {
+        return Ok(PathBuf::from(url));
+    }
+    url::Url::parse(url)
+        .ok()
+        .and_then(|parsed| parsed.to_file_path().ok())
+        .ok_or_else(|| Error::Transport {
+            url: url.into(),
+            message: "Not a local file".into(),
+        })
+}
+
+fn is_file_url(url: &str) -> bool {
+    matches!(url.get(..7), Some(scheme) if scheme.eq_ignore_ascii_case("file://"))
+}
+
+// The `file://` URL of a plain path, so relative links in the file can be
+// resolved against it.
+pub(crate) fn file_url(path: &str) -> Option<url::Url> {
+    let path = std::env::current_dir().ok()?.join(path);
+    url::Url::from_file_path(path).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fetch::Limit;
+    use std::fs;
+
+    #[test]
+    fn fetch_local_files() {
+        let dir = std::env::temp_dir().join(format!("rustwtxt-file-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("twtxt.txt");
+        fs::write(
+            &path,
+            "# nick = foo\n# prev = old.txt\n2020-02-01T00:00:00Z\thi\n",
+        )
+        .unwrap();
+        fs::write(dir.join("old.txt"), "2020-01-01T00:00:00Z\tolder\n").unwrap();
+
+        let plain = path.to_str().unwrap();
+        let twtxt = crate::Twtxt::from(plain).unwrap();
+        assert_eq!("foo", twtxt.nick());
+        assert_eq!(plain, twtxt.url());
+        assert_eq!(2, twtxt.fetch_history(1).0.tweets().len());
+
+        let url = url::Url::from_file_path(&path).unwrap().to_string();
+        let cached = match FileFetcher::new().fetch(&url, None).unwrap() {
+            FetchOutcome::Modified(fetched) => fetched,
+            FetchOutcome::NotModified => panic!("nothing was cached"),
+        };
+        match FileFetcher::new().fetch(&url, Some(&cached)).unwrap() {
+            FetchOutcome::NotModified => {}
+            FetchOutcome::Modified(_) => panic!("the file hasn't changed"),
+        }
+
+        match FileFetcher::new().fetch(&format!("{}.missing", url), None) {
+            Err(Error::Transport { .. }) => {}
+            other => panic!("unexpected outcome: {:?}", other),
+        }
+
+        let options = FetchOptions {
+            max_tweets: 0,
+            ..FetchOptions::default()
+        };
+        let fetcher = FileFetcher::new().with_options(options);
+        assert!(crate::Twtxt::from_with(&fetcher, &url)
+            .unwrap()
+            .tweets()
+            .is_empty());
+
+        let options = FetchOptions {
+            max_body_size: 16,
+            ..FetchOptions::default()
+        };
+        match FileFetcher::new().with_options(options).fetch(&url, None) {
+            Err(Error::Limit { limit, .. }) => assert_eq!(Limit::BodySize, limit),
+            other => panic!("unexpected outcome: {:?}", other),
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,374 @@
+//! Fetching `twtxt.txt` files over Gemini, trusting the certificate each
+//! host presents the first time it's seen and refusing it if it changes.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use blake2::digest::consts::U32;
+use blake2::{Blake2b, Digest};
+use data_encoding::HEXLOWER;
+use rustls::{
+    Certificate, ClientConfig, ClientSession, RootCertStore, ServerCertVerified,
+    ServerCertVerifier, StreamOwned, TLSError,
+};
+
+use super::{
+    connect, io_error, read_limited, FetchOptions, FetchOutcome, Fetched, Fetcher, Redirect,
+    TimedStream,
+};
+use crate::{Error, Result};
+
+const DEFAULT_PORT: u16 = 1965;
+const MAX_REDIRECTS: usize = 5;
+
+/// Fetches over Gemini. Certificates are trusted on first use: the
+/// fingerprint of the certificate each host presents is remembered, and
+/// a host presenting a different one later is refused.
+///
+/// Fingerprints are only kept in memory unless a known hosts file is
+/// given with `GeminiFetcher::with_known_hosts()`.
+#[derive(Debug, Clone, Default)]
+pub struct GeminiFetcher {
+    known_hosts: Arc<Mutex<BTreeMap<String, String>>>,
+    path: Option<PathBuf>,
+    options: FetchOptions,
+}
+
+impl GeminiFetcher {
+    /// Creates a fetcher remembering certificates in memory.
+    pub fn new() -> GeminiFetcher {
+        GeminiFetcher::default()
+    }
+
+    /// Creates a fetcher remembering certificates in the given file,
+    /// which is created when the first host is seen.
+    pub fn with_known_hosts<P: AsRef<Path>>(path: P) -> Result<GeminiFetcher> {
+        let path = path.as_ref();
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(err) => return Err(Error::Io(err)),
+        };
+        let known_hosts = contents
+            .lines()
+            .filter_map(|line| {
+                let mut split = line.split_whitespace();
+                Some((split.next()?.to_string(), split.next()?.to_string()))
+            })
+            .collect();
+
+        Ok(GeminiFetcher {
+            known_hosts: Arc::new(Mutex::new(known_hosts)),
+            path: Some(path.to_path_buf()),
+            options: FetchOptions::default(),
+        })
+    }
+
+    /// Limits what each fetch may download.
+    pub fn with_options(mut self, options: FetchOptions) -> GeminiFetcher {
+        self.options = options;
+        self
+    }
+
+    /// The fingerprint trusted for each `host:port`.
+    pub fn known_hosts(&self) -> BTreeMap<String, String> {
+        match self.known_hosts.lock() {
+            Ok(known_hosts) => known_hosts.clone(),
+            Err(_) => BTreeMap::new(),
+        }
+    }
+
+    // Sends a request, returning the status, the meta line and the body.
+    fn request(&self, url: &url::Url) -> Result<(u8, String, Vec<u8>)> {
+        let transport = |message: &str| Error::Transport {
+            url: url.to_string(),
+            message: message.into(),
+        };
+
+        let host = url.host_str().ok_or_else(|| transport("Missing host"))?;
+        let port = url.port().unwrap_or(DEFAULT_PORT);
+        let dns_name = webpki::DNSNameRef::try_from_ascii_str(host)
+            .map_err(|_| transport("Gemini hosts must be named, not IP addresses"))?;
+
+        let mut config = ClientConfig::new();
+        config.dangerous().set_certificate_verifier(Arc::new(Tofu {
+            host: format!("{}:{}", host, port),
+            fetcher: self.clone(),
+        }));
+        let session = ClientSession::new(&Arc::new(config), dns_name);
+        let started = Instant::now();
+        let sock = connect(host, port).map_err(|err| Error::from_io(url.as_str(), &err))?;
+        // The handshake happens as the request is written, and is held to
+        // the read timeout like the rest of the response.
+        let sock = TimedStream::new(sock, &self.options, started);
+        let mut stream = StreamOwned::new(session, sock);
+
+        stream
+            .write_all(format!("{}\r\n", url).as_bytes())
+            .map_err(|err| io_error(url.as_str(), &err, &self.options, started))?;
+        let mut response =
+            read_limited(url.as_str(), Unterminated(stream), &self.options, started)?;
+
+        let end = response
+            .windows(2)
+            .position(|window| window == b"\r\n")
+            .ok_or_else(|| transport("Malformed response header"))?;
+        let header = String::from_utf8_lossy(&response[..end]).to_string();
+        let status = header
+            .get(..2)
+            .and_then(|status| status.parse().ok())
+            .ok_or_else(|| transport("Malformed response header"))?;
+        let meta = header[2..].trim().to_string();
+
+        Ok((status, meta, response.split_off(end + 2)))
+    }
+
+    // Trusts the fingerprint for the host if none is known yet.
+    fn check(&self, host: &str, fingerprint: String) -> std::result::Result<(), TLSError> {
+        let mut known_hosts = self
+            .known_hosts
+            .lock()
+            .map_err(|_| TLSError::General("Known hosts unavailable".into()))?;
+        match known_hosts.get(host) {
+            Some(known) if *known == fingerprint => return Ok(()),
+            Some(_) => {
+                return Err(TLSError::General(format!(
+                    "The certificate for {} has changed",
+                    host
+                )))
+            }
+            None => known_hosts.insert(host.into(), fingerprint),
+        };
+
+        if let Some(path) = &self.path {
+            let contents = known_hosts
+                .iter()
+                .map(|(host, fingerprint)| format!("{} {}\n", host, fingerprint))
+                .collect::<String>();
+            fs::write(path, contents)
+                .map_err(|err| TLSError::General(format!("Couldn't save known hosts: {}", err)))?;
+        }
+        Ok(())
+    }
+}
+
+impl Fetcher for GeminiFetcher {
+    fn fetch(&self, url: &str, _cached: Option<&Fetched>) -> Result<FetchOutcome> {
+        let mut current = url::Url::parse(url).map_err(|err| Error::Transport {
+            url: url.into(),
+            message: err.to_string(),
+        })?;
+
+        let mut redirects = Vec::new();
+        while redirects.len() <= MAX_REDIRECTS {
+            let (status, meta, body) = self.request(&current)?;
+            match status {
+                20..=29 => {
+                    let body =
+                        String::from_utf8(body).map_err(|_| Error::Encoding { url: url.into() })?;
+                    return Ok(FetchOutcome::Modified(Fetched {
+                        body,
+                        redirects,
+                        ..Fetched::default()
+                    }));
+                }
+                30..=39 => {
+                    let to = current.join(&meta).map_err(|err| Error::Transport {
+                        url: url.into(),
+                        message: err.to_string(),
+                    })?;
+                    redirects.push(Redirect {
+                        status: status.into(),
+                        from: current.to_string(),
+                        to: to.to_string(),
+                    });
+                    current = to;
+                }
+                _ => {
+                    return Err(Error::Transport {
+                        url: url.into(),
+                        message: format!("Gemini status {}: {}", status, meta),
+                    })
+                }
+            }
+        }
+
+        Err(Error::Transport {
+            url: url.into(),
+            message: "Too many redirects".into(),
+        })
+    }
+
+    fn options(&self, _url: &str) -> FetchOptions {
+        self.options
+    }
+}
+
+// Servers often close the connection without saying goodbye, which
+// ends the response all the same.
+struct Unterminated<R>(R);
+
+impl<R: Read> Read for Unterminated<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.0.read(buf) {
+            Err(err) if err.kind() == io::ErrorKind::ConnectionAborted => Ok(0),
+            read => read,
+        }
+    }
+}
+
+// Trusts a host's certificate on first use.
+struct Tofu {
+    host: String,
+    fetcher: GeminiFetcher,
+}
+
+impl ServerCertVerifier for Tofu {
+    fn verify_server_cert(
+        &self,
+        _roots: &RootCertStore,
+        presented_certs: &[Certificate],
+        _dns_name: webpki::DNSNameRef,
+        _ocsp_response: &[u8],
+    ) -> std::result::Result<ServerCertVerified, TLSError> {
+        let cert = presented_certs
+            .first()
+            .ok_or(TLSError::NoCertificatesPresented)?;
+        let fingerprint = HEXLOWER.encode(&Blake2b::<U32>::digest(&cert.0));
+        self.fetcher.check(&self.host, fingerprint)?;
+        Ok(ServerCertVerified::assertion())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fetch::Limit;
+    use rustls::{NoClientAuth, PrivateKey, ServerConfig, ServerSession, Session};
+    use std::io::BufRead;
+    use std::net::TcpListener;
+    use std::time::Duration;
+
+    // Serves requests with a self-signed certificate for `localhost`,
+    // answering each with `respond(request line)`.
+    fn serve(requests: usize, respond: fn(&str) -> String) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let cert = rcgen::generate_simple_self_signed(vec!["localhost".into()]).unwrap();
+        let mut config = ServerConfig::new(NoClientAuth::new());
+        config
+            .set_single_cert(
+                vec![Certificate(cert.serialize_der().unwrap())],
+                PrivateKey(cert.serialize_private_key_der()),
+            )
+            .unwrap();
+        let config = Arc::new(config);
+
+        std::thread::spawn(move || {
+            for _ in 0..requests {
+                let (sock, _) = listener.accept().unwrap();
+                let session = ServerSession::new(&config);
+                let mut stream = io::BufReader::new(StreamOwned::new(session, sock));
+                let mut request = String::new();
+                if stream.read_line(&mut request).is_err() {
+                    continue;
+                }
+                let response = respond(request.trim_end());
+                let stream = stream.get_mut();
+                let _ = stream.write_all(response.as_bytes());
+                stream.sess.send_close_notify();
+                let _ = stream.flush();
+            }
+        });
+        port
+    }
+
+    fn respond(request: &str) -> String {
+        let path = request.splitn(4, '/').nth(3).unwrap_or_default();
+        match path {
+            "twtxt.txt" => "20 text/plain\r\n# nick = foo\n2020-01-01T00:00:00Z\thi\n".into(),
+            "old.txt" => "31 /twtxt.txt\r\n".into(),
+            _ => "51 Not found\r\n".into(),
+        }
+    }
+
+    #[test]
+    fn fetch_over_gemini() {
+        let port = serve(5, respond);
+        let fetcher = GeminiFetcher::new();
+
+        let url = format!("gemini://localhost:{}/old.txt", port);
+        let twtxt = crate::Twtxt::from_with(&fetcher, &url).unwrap();
+        assert_eq!("foo", twtxt.nick());
+        match fetcher.fetch(&url, None).unwrap() {
+            FetchOutcome::Modified(fetched) => assert_eq!(
+                Some(format!("gemini://localhost:{}/twtxt.txt", port).as_str()),
+                fetched.moved_to()
+            ),
+            other => panic!("unexpected outcome: {:?}", other),
+        }
+        assert_eq!(1, fetcher.known_hosts().len());
+
+        match fetcher.fetch(&format!("gemini://localhost:{}/nope.txt", port), None) {
+            Err(Error::Transport { message, .. }) => {
+                assert_eq!("Gemini status 51: Not found", message)
+            }
+            other => panic!("unexpected outcome: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn changed_certificate() {
+        let port = serve(2, respond);
+        let path = std::env::temp_dir().join(format!("rustwtxt-known-hosts-{}", port));
+        let url = format!("gemini://localhost:{}/twtxt.txt", port);
+
+        let fetcher = GeminiFetcher::with_known_hosts(&path).unwrap();
+        assert!(fetcher.fetch(&url, None).is_ok());
+        let fetcher = GeminiFetcher::with_known_hosts(&path).unwrap();
+        assert_eq!(1, fetcher.known_hosts().len());
+
+        fs::write(&path, format!("localhost:{} 00ff\n", port)).unwrap();
+        let fetcher = GeminiFetcher::with_known_hosts(&path).unwrap();
+        match fetcher.fetch(&url, None) {
+            Err(Error::Transport { message, .. }) => assert!(message.contains("has changed")),
+            other => panic!("unexpected outcome: {:?}", other),
+        }
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn slow_handshake() {
+        // Starts a TLS record too long to ever finish, sending a byte of
+        // it every 50ms, each well inside the read timeout.
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        std::thread::spawn(move || {
+            let (mut sock, _) = listener.accept().unwrap();
+            let mut buf = [0; 1024];
+            let _ = sock.read(&mut buf);
+            let _ = sock.write_all(&[0x16, 0x03, 0x03, 0x40, 0x00]);
+            for _ in 0..60 {
+                std::thread::sleep(Duration::from_millis(50));
+                if sock.write_all(&[0]).is_err() {
+                    return;
+                }
+            }
+        });
+
+        let fetcher = GeminiFetcher::new().with_options(FetchOptions {
+            read_timeout: Duration::from_millis(300),
+            ..FetchOptions::default()
+        });
+        let started = Instant::now();
+        match fetcher.fetch(&format!("gemini://localhost:{}/twtxt.txt", port), None) {
+            Err(Error::Limit { limit, .. }) => assert_eq!(Limit::ReadTimeout, limit),
+            other => panic!("unexpected outcome: {:?}", other),
+        }
+        assert!(started.elapsed() < Duration::from_millis(1000));
+    }
+}
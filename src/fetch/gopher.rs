@@ -0,0 +1,130 @@
+//! Fetching `twtxt.txt` files over Gopher, from URLs such as
+//! `gopher://example.com/0/~alice/twtxt.txt`.
+
+use std::io::Write;
+use std::time::Instant;
+
+use super::{
+    connect, io_error, read_limited, FetchOptions, FetchOutcome, Fetched, Fetcher, TimedStream,
+};
+use crate::{Error, Result};
+
+const DEFAULT_PORT: u16 = 70;
+
+/// Fetches over Gopher. The item type in the URL is skipped over, and
+/// the rest of the path is sent as the selector.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GopherFetcher {
+    options: FetchOptions,
+}
+
+impl GopherFetcher {
+    /// Creates a fetcher with the default limits.
+    pub fn new() -> GopherFetcher {
+        GopherFetcher::default()
+    }
+
+    /// Limits what each fetch may download.
+    pub fn with_options(mut self, options: FetchOptions) -> GopherFetcher {
+        self.options = options;
+        self
+    }
+}
+
+impl Fetcher for GopherFetcher {
+    fn fetch(&self, url: &str, _cached: Option<&Fetched>) -> Result<FetchOutcome> {
+        let transport = |message: String| Error::Transport {
+            url: url.into(),
+            message,
+        };
+
+        let parsed = url::Url::parse(url).map_err(|err| transport(err.to_string()))?;
+        let host = parsed
+            .host_str()
+            .ok_or_else(|| transport("Missing host".into()))?;
+        let port = parsed.port().unwrap_or(DEFAULT_PORT);
+
+        let started = Instant::now();
+        let stream = connect(host, port).map_err(|err| Error::from_io(url, &err))?;
+        let mut stream = TimedStream::new(stream, &self.options, started);
+        let request = format!("{}\r\n", selector(parsed.path()));
+        stream
+            .write_all(request.as_bytes())
+            .map_err(|err| io_error(url, &err, &self.options, started))?;
+        let body = read_limited(url, stream, &self.options, started)?;
+
+        let body = String::from_utf8(body).map_err(|_| Error::Encoding { url: url.into() })?;
+        Ok(FetchOutcome::Modified(Fetched {
+            body: strip_terminator(body),
+            ..Fetched::default()
+        }))
+    }
+
+    fn options(&self, _url: &str) -> FetchOptions {
+        self.options
+    }
+}
+
+// `/0/~alice/twtxt.txt` is item type `0` and selector `/~alice/twtxt.txt`.
+fn selector(path: &str) -> &str {
+    let path = path.strip_prefix('/').unwrap_or(path);
+    let mut chars = path.chars();
+    match chars.next() {
+        Some(_) => chars.as_str(),
+        None => "",
+    }
+}
+
+// Text items may end with a line holding a lone `.`.
+fn strip_terminator(mut body: String) -> String {
+    for terminator in &[".\r\n", ".\n", "."] {
+        let kept = match body.strip_suffix(terminator) {
+            Some(kept) if kept.is_empty() || kept.ends_with('\n') => kept.len(),
+            _ => continue,
+        };
+        body.truncate(kept);
+        break;
+    }
+    body
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::BufRead;
+    use std::net::TcpListener;
+
+    #[test]
+    fn selectors() {
+        assert_eq!("/~alice/twtxt.txt", selector("/0/~alice/twtxt.txt"));
+        assert_eq!("", selector("/"));
+        assert_eq!("", selector(""));
+        assert_eq!(
+            "# nick = foo\n",
+            strip_terminator("# nick = foo\n.\r\n".into())
+        );
+        assert_eq!("see ...\n", strip_terminator("see ...\n".into()));
+    }
+
+    #[test]
+    fn fetch_over_gopher() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut selector = String::new();
+            let mut reader = std::io::BufReader::new(stream);
+            reader.read_line(&mut selector).unwrap();
+            let body = match selector.as_str() {
+                "/~foo/twtxt.txt\r\n" => "# nick = foo\r\n2020-01-01T00:00:00Z\thi\r\n.\r\n",
+                _ => "3Not found\terror\terror.host\t1\r\n",
+            };
+            reader.get_mut().write_all(body.as_bytes()).unwrap();
+        });
+
+        let url = format!("gopher://127.0.0.1:{}/0/~foo/twtxt.txt", port);
+        let twtxt = crate::Twtxt::from(&url).unwrap();
+        assert_eq!("foo", twtxt.nick());
+        assert_eq!(1, twtxt.tweets().len());
+    }
+}
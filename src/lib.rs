@@ -3,24 +3,74 @@
 //! This is a library intended to make working with `twtxt` timelines
 //! a bit easier.
 
-use std::collections::BTreeMap;
-use std::str::FromStr;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
 
-use failure::format_err;
+use chrono::{DateTime, FixedOffset, SecondsFormat};
+use lazy_static::lazy_static;
 use regex::Regex;
-use ureq;
 
+use report::Problem;
+
+pub mod conversation;
+pub mod error;
+pub mod fetch;
+pub mod mention;
+pub mod metadata;
 pub mod parse;
+pub mod parser;
+pub mod report;
+#[cfg(test)]
+pub(crate) mod test_server;
+pub mod token;
+
+pub use conversation::{Conversation, Subject};
+pub use error::{Error, Result};
+pub use fetch::{ClientIdentity, FetchOptions, FetchOutcome, Fetched, Fetcher, Registry};
+pub use mention::Mention;
+pub use metadata::Metadata;
+pub use parser::{Item, Parser};
+pub use report::{ParseOptions, ParseReport};
+pub use token::Token;
+
+/// Separates the lines of a multi-line tweet, as newlines would otherwise
+/// end the tweet.
+pub const LINE_SEPARATOR: char = '\u{2028}';
 
-type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
-pub type TweetMap = std::collections::BTreeMap<String, Tweet>;
+/// Identifies a tweet within a `twtxt.txt` file: the instant it was
+/// posted, then its position in the file, so tweets posted in the same
+/// second are kept apart.
+pub type TweetKey = (DateTime<FixedOffset>, usize);
+
+/// Tweets keyed by the instant they were posted, so iteration is
+/// chronological regardless of the UTC offset each feed uses. Tweets
+/// sharing an instant are kept in the order they appear in the file.
+pub type TweetMap = std::collections::BTreeMap<TweetKey, Tweet>;
+
+lazy_static! {
+    static ref TAGS_REGEX: Regex = Regex::new(r"(^|\s)#[^\s]+").unwrap();
+}
 
 /// Holds tweets and metadata from a single `twtxt.txt` file.
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct Twtxt {
     nickname: String,
     url: String,
+    metadata: Metadata,
     tweets: TweetMap,
+    lines: Vec<(Line, &'static str)>,
+    report: ParseReport,
+}
+
+// A line of the original file, kept with the line ending it had. Anything
+// that isn't a tweet is kept verbatim so the file can be written back out
+// unchanged.
+#[derive(Debug, Clone, Eq, PartialEq)]
+enum Line {
+    Text(String),
+    Tweet(TweetKey),
 }
 
 impl Twtxt {
@@ -34,132 +84,629 @@ impl Twtxt {
         &self.url
     }
 
-    /// Returns a specific tweet by the timestamp key.
+    /// Returns the metadata declared in the comment header of
+    /// the `twtxt.txt` file.
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    /// Returns a specific tweet by its timestamp. The timestamp is
+    /// compared as an instant, so `2020-01-01T10:00:00+02:00` will find
+    /// a tweet stamped `2020-01-01T08:00:00Z`. If several tweets share
+    /// the instant, the first in the file is returned.
     pub fn tweet(&self, datestamp: &str) -> Option<&Tweet> {
-        if self.tweets.contains_key(datestamp) {
-            Some(&self.tweets[datestamp])
-        } else {
-            None
+        self.tweets_at(datestamp).next()
+    }
+
+    /// Returns every tweet posted at the given instant, in the order
+    /// they appear in the file.
+    pub fn tweets_at(&self, datestamp: &str) -> impl Iterator<Item = &Tweet> {
+        let range = parse::timestamp(datestamp)
+            .ok()
+            .map(|timestamp| self.tweets.range((timestamp, 0)..=(timestamp, usize::MAX)));
+        range.into_iter().flatten().map(|(_, tweet)| tweet)
+    }
+
+    /// Returns the problems found while parsing the file.
+    pub fn report(&self) -> &ParseReport {
+        &self.report
+    }
+
+    /// Returns a specific tweet by its twt hash.
+    pub fn tweet_by_hash(&self, hash: &str) -> Option<&Tweet> {
+        let url = self.hash_url();
+        self.tweets.values().find(|tweet| tweet.hash(url) == hash)
+    }
+
+    /// Returns the twt hash of a tweet from this file.
+    pub fn tweet_hash(&self, tweet: &Tweet) -> String {
+        tweet.hash(self.hash_url())
+    }
+
+    /// The URL used when computing twt hashes: the first `url` declared
+    /// in the metadata, falling back to the URL the file was read from.
+    pub fn hash_url(&self) -> &str {
+        match self.metadata.urls().first() {
+            Some(url) => url,
+            None => &self.url,
         }
     }
 
     /// Returns all tweets as a `TweetMap`, a thin wrapper around a `BTreeMap`.
-    /// The tweets will be date-sorted.
+    /// The tweets will be sorted chronologically.
     pub fn tweets(&self) -> &TweetMap {
         &self.tweets
     }
 
-    /// Parse a remote `twtxt.txt` file into a `Twtxt` structure.
-    pub fn from(url: &str) -> Option<Twtxt> {
-        let twtxt = if let Ok(val) = pull_twtxt(&url) {
-            val
-        } else {
-            return None;
-        };
+    /// Drops all but the `count` newest tweets, such as to apply
+    /// `FetchOptions::max_tweets` to a feed that has grown huge.
+    pub fn keep_newest(&mut self, count: usize) {
+        let excess = self.tweets.len().saturating_sub(count);
+        let dropped = self.tweets.keys().take(excess).cloned().collect::<Vec<_>>();
+        dropped.iter().for_each(|key| {
+            self.tweets.remove(key);
+        });
 
-        let url = url.to_owned();
+        let tweets = &self.tweets;
+        self.lines.retain(|(line, _)| match line {
+            Line::Tweet(key) => tweets.contains_key(key),
+            Line::Text(_) => true,
+        });
+    }
 
-        let nickname = if let Ok(val) = parse::metadata(&twtxt, "nick") {
-            val
-        } else {
-            return None;
-        };
+    /// Parse a remote `twtxt.txt` file into a `Twtxt` structure. Local
+    /// files can be given as `file://` URLs or plain paths.
+    pub fn from(url: &str) -> Result<Twtxt> {
+        Twtxt::from_with(&Registry::default(), url)
+    }
 
-        let mut tweets = BTreeMap::new();
-        twtxt
-            .split('\n')
-            .collect::<Vec<&str>>()
-            .iter()
-            .for_each(|line| {
-                if line.starts_with('#') || line == &"" || !line.contains('\t') {
-                    return;
+    /// Parse a remote `twtxt.txt` file, downloading it with the given
+    /// fetcher. Only the newest `FetchOptions::max_tweets` tweets the
+    /// fetcher allows are kept.
+    pub fn from_with<F: Fetcher + ?Sized>(fetcher: &F, url: &str) -> Result<Twtxt> {
+        let options = fetcher.options(url);
+        let body = pull_with(fetcher, url)?;
+        let parser = Parser::new(body.as_bytes()).with_max_line_length(options.max_line_length);
+        let mut twtxt = Twtxt::from_parser(parser, url, &ParseOptions::default(), None)?;
+        twtxt.keep_newest(options.max_tweets);
+        Ok(twtxt)
+    }
+
+    /// Follows the `prev` links in the metadata to archived feeds, at most
+    /// `depth` archives deep, and returns this feed with every archived
+    /// tweet merged in. Relative links are resolved against the URL of the
+    /// feed naming them. The walk stops at a link back to a feed it has
+    /// already seen. Links must keep the scheme of the feed, so a remote
+    /// feed can't point at a local file or another protocol; `http` and
+    /// `https` count as one.
+    ///
+    /// If an archive can't be fetched or parsed, the walk stops there and
+    /// the tweets merged so far are returned along with the error.
+    pub fn fetch_history(&self, depth: usize) -> (Twtxt, Option<Error>) {
+        self.fetch_history_with(&Registry::default(), depth)
+    }
+
+    /// Fetches archived tweets with the given fetcher. See
+    /// `Twtxt::fetch_history()`.
+    pub fn fetch_history_with<F: Fetcher + ?Sized>(
+        &self,
+        fetcher: &F,
+        depth: usize,
+    ) -> (Twtxt, Option<Error>) {
+        let mut merged = self.clone();
+        let error = self.merge_history(fetcher, depth, &mut merged).err();
+        (merged, error)
+    }
+
+    // Merges each archive into `merged` as soon as it has been read, so
+    // a broken link doesn't lose the archives before it, nor the archive
+    // naming it.
+    fn merge_history<F: Fetcher + ?Sized>(
+        &self,
+        fetcher: &F,
+        depth: usize,
+        merged: &mut Twtxt,
+    ) -> Result<()> {
+        let mut seen = vec![self.url.clone(), self.hash_url().to_string()];
+        let mut next = self.prev_url()?;
+
+        for _ in 0..depth {
+            let url = match next {
+                Some(url) if !seen.contains(&url) => url,
+                _ => break,
+            };
+            let twtxt = pull_with(fetcher, &url)?;
+            let archive = Twtxt::from_parser(
+                Parser::new(twtxt.as_bytes())
+                    .with_max_line_length(fetcher.options(&url).max_line_length),
+                &url,
+                &ParseOptions::default(),
+                Some(self.nick()),
+            )?;
+
+            let prev = archive.prev_url();
+            seen.push(url);
+            // Archives may repeat tweets already merged, which are kept once.
+            let feed_url = self.hash_url();
+            for tweet in archive.tweets.into_values() {
+                let hash = tweet.hash(feed_url);
+                let ts = tweet.timestamp;
+                let known = merged
+                    .tweets
+                    .range((ts, 0)..=(ts, usize::MAX))
+                    .any(|(_, merged)| merged.hash(feed_url) == hash);
+                if !known {
+                    merged.push(tweet);
                 }
-                let tweet = if let Ok(val) = Tweet::from_str(line) {
-                    val
-                } else {
-                    return;
-                };
-                tweets.insert(tweet.timestamp.clone(), tweet);
+            }
+            next = prev?;
+        }
+
+        Ok(())
+    }
+
+    // The `prev` archive, resolved against the URL of this feed.
+    fn prev_url(&self) -> Result<Option<String>> {
+        let prev = match self.metadata.prev() {
+            Some(prev) => prev,
+            None => return Ok(None),
+        };
+        let transport = |err: url::ParseError| Error::Transport {
+            url: self.url.clone(),
+            message: err.to_string(),
+        };
+
+        let base = match url::Url::parse(&self.url) {
+            Err(url::ParseError::RelativeUrlWithoutBase) => fetch::file::file_url(&self.url)
+                .ok_or(url::ParseError::RelativeUrlWithoutBase)
+                .map_err(transport)?,
+            parsed => parsed.map_err(transport)?,
+        };
+        let url = base.join(&prev.file).map_err(transport)?;
+        if web_scheme(base.scheme()) != web_scheme(url.scheme()) {
+            return Err(Error::Transport {
+                url: url.to_string(),
+                message: format!("Archive isn't served over {}", base.scheme()),
             });
+        }
+        Ok(Some(url.to_string()))
+    }
+
+    /// Parse the contents of a `twtxt.txt` file that has already been
+    /// retrieved. The URL is recorded as the location of the file.
+    /// The file must declare a `nick` in its metadata.
+    ///
+    /// # Examples
+    /// ```
+    /// # use rustwtxt::Twtxt;
+    /// let contents = "# nick = foo\n2020-01-01T00:00:00Z\thello\n";
+    /// let twtxt = Twtxt::parse(contents, "https://example.com/twtxt.txt").unwrap();
+    /// assert_eq!("foo", twtxt.nick());
+    /// assert_eq!(1, twtxt.tweets().len());
+    /// ```
+    pub fn parse(twtxt: &str, url: &str) -> Result<Twtxt> {
+        Twtxt::parse_with(twtxt, url, &ParseOptions::default())
+    }
+
+    /// Parse the contents of a `twtxt.txt` file, choosing what happens to
+    /// malformed lines. In lenient mode they are skipped and listed in
+    /// `Twtxt::report()`; in strict mode the first one is returned as
+    /// `Error::MalformedLine`.
+    ///
+    /// # Examples
+    /// ```
+    /// # use rustwtxt::{ParseOptions, Twtxt};
+    /// let contents = "# nick = foo\nyesterday\thello\n";
+    /// let url = "https://example.com/twtxt.txt";
+    ///
+    /// let twtxt = Twtxt::parse_with(contents, url, &ParseOptions::lenient()).unwrap();
+    /// assert_eq!(2, twtxt.report().diagnostics()[0].line);
+    /// assert!(Twtxt::parse_with(contents, url, &ParseOptions::strict()).is_err());
+    /// ```
+    pub fn parse_with(twtxt: &str, url: &str, options: &ParseOptions) -> Result<Twtxt> {
+        Twtxt::from_parser(uncapped(twtxt.as_bytes()), url, options, None)
+    }
 
-        Some(Twtxt {
+    /// Parse a `twtxt.txt` file from any buffered reader, such as
+    /// a cached copy or an archive.
+    pub fn from_reader<R: BufRead>(reader: R, url: &str) -> Result<Twtxt> {
+        Twtxt::from_parser(uncapped(reader), url, &ParseOptions::default(), None)
+    }
+
+    /// Parse a `twtxt.txt` file from any buffered reader, using `nick` if
+    /// the file doesn't declare one, as your own feed may not.
+    pub fn from_reader_with_nick<R: BufRead>(reader: R, url: &str, nick: &str) -> Result<Twtxt> {
+        Twtxt::from_parser(uncapped(reader), url, &ParseOptions::default(), Some(nick))
+    }
+
+    // `nick` is used when the file doesn't declare one, as archives
+    // often don't.
+    fn from_parser<R: BufRead>(
+        mut parser: Parser<R>,
+        url: &str,
+        options: &ParseOptions,
+        nick: Option<&str>,
+    ) -> Result<Twtxt> {
+        let mut metadata = Metadata::default();
+        let mut tweets = TweetMap::new();
+        let mut lines = Vec::new();
+        let mut report = ParseReport::default();
+        while let Some(item) = parser.next() {
+            let line = parser.line();
+            let ending = parser.ending();
+            let tweet = match item {
+                Ok(Item::Tweet(tweet)) => tweet,
+                Ok(Item::Metadata { key, value, line }) => {
+                    metadata.insert(&key, &value);
+                    lines.push((Line::Text(line), ending));
+                    continue;
+                }
+                Ok(Item::Comment(text)) | Ok(Item::Blank(text)) => {
+                    lines.push((Line::Text(text), ending));
+                    continue;
+                }
+                Err(Error::MalformedLine {
+                    line,
+                    content,
+                    problem,
+                }) => {
+                    if options.strict {
+                        return Err(Error::MalformedLine {
+                            line,
+                            content,
+                            problem,
+                        });
+                    }
+                    report.push(line, &content, problem);
+                    lines.push((Line::Text(content), ending));
+                    continue;
+                }
+                Err(Error::Io(err)) if err.kind() == std::io::ErrorKind::InvalidData => {
+                    return Err(Error::Encoding { url: url.into() });
+                }
+                Err(err) => return Err(err),
+            };
+
+            let mut problems = Vec::new();
+            if tweet.body.chars().any(|c| c.is_control() && c != '\t') {
+                problems.push(Problem::ControlCharacters);
+            }
+            let key = (tweet.timestamp, line);
+            if tweets.range((tweet.timestamp, 0)..key).next().is_some() {
+                problems.push(Problem::DuplicateTimestamp);
+            }
+            for problem in problems {
+                if options.strict {
+                    return Err(Error::MalformedLine {
+                        line,
+                        content: tweet.to_string(),
+                        problem,
+                    });
+                }
+                report.push(line, &tweet.to_string(), problem);
+            }
+
+            tweets.insert(key, tweet);
+            lines.push((Line::Tweet(key), ending));
+        }
+
+        let nickname = match metadata.nick().or(nick) {
+            Some(val) => val.to_string(),
+            None => return Err(Error::MissingMetadata("nick".into())),
+        };
+
+        Ok(Twtxt {
             nickname,
-            url,
+            url: url.into(),
+            metadata,
             tweets,
+            lines,
+            report,
         })
     }
+
+    /// Parse a local `twtxt.txt` file. The path is recorded as
+    /// the URL of the file.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Twtxt> {
+        let path = path.as_ref();
+        let file = File::open(path)?;
+
+        Twtxt::from_reader(BufReader::new(file), &path.to_string_lossy())
+    }
+
+    /// Adds a tweet to the end of the file. Existing tweets with the same
+    /// timestamp are kept.
+    pub fn push(&mut self, tweet: Tweet) {
+        // Sorts after every tweet already in the file. Lines may have been
+        // dropped by `keep_newest()`, so their count won't do.
+        let seq = self.tweets.keys().map(|(_, seq)| *seq).max().unwrap_or(0);
+        let key = (tweet.timestamp, seq + 1);
+        self.tweets.insert(key, tweet);
+
+        // New lines end like the rest of the file, and a file without
+        // a trailing newline still ends without one.
+        let newline = self
+            .lines
+            .iter()
+            .rev()
+            .map(|(_, ending)| *ending)
+            .find(|ending| !ending.is_empty())
+            .unwrap_or("\n");
+        let ending = match self.lines.last_mut() {
+            Some((_, ending)) if ending.is_empty() => {
+                *ending = newline;
+                ""
+            }
+            Some(_) => newline,
+            None => "",
+        };
+        self.lines.push((Line::Tweet(key), ending));
+    }
+
+    /// Writes the `twtxt.txt` file out. Comments, metadata and blank lines
+    /// are written where they appeared in the original file, and an
+    /// unmodified file is reproduced exactly.
+    pub fn write_to<W: Write>(&self, mut writer: W) -> std::io::Result<()> {
+        write!(writer, "{}", self)
+    }
+}
+
+impl fmt::Display for Twtxt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (line, ending) in &self.lines {
+            match line {
+                Line::Text(text) => f.write_str(text)?,
+                Line::Tweet(key) => write!(f, "{}", self.tweets[key])?,
+            }
+            f.write_str(ending)?;
+        }
+        Ok(())
+    }
 }
 
 /// Holds a single tweet.
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct Tweet {
-    timestamp: String,
+    timestamp: DateTime<FixedOffset>,
+    raw_timestamp: String,
     body: String,
-    mentions: Vec<String>,
+    subject: Option<Subject>,
+    mentions: Vec<Mention>,
     tags: Vec<String>,
 }
 
 impl Tweet {
-    /// Returns the timestamp for a given tweet.
-    pub fn timestamp(&self) -> &str {
-        &self.timestamp
+    /// Creates a new tweet. The timestamp will be written in `RFC 3339`
+    /// format, to the second, and any newlines in the text will be encoded
+    /// as `U+2028` line separators.
+    ///
+    /// # Examples
+    /// ```
+    /// # use rustwtxt::Tweet;
+    /// # use chrono::prelude::*;
+    /// let now = Utc.ymd(2020, 1, 1).and_hms(0, 0, 0).into();
+    /// let tweet = Tweet::new(now, "hello #world");
+    /// assert_eq!("2020-01-01T00:00:00Z\thello #world", tweet.to_string());
+    /// ```
+    pub fn new(timestamp: DateTime<FixedOffset>, text: &str) -> Tweet {
+        let raw_timestamp = timestamp.to_rfc3339_opts(SecondsFormat::Secs, true);
+        let body = text
+            .lines()
+            .collect::<Vec<&str>>()
+            .join(&LINE_SEPARATOR.to_string());
+        Tweet::with_body(timestamp, raw_timestamp, body)
+    }
+
+    fn with_body(timestamp: DateTime<FixedOffset>, raw_timestamp: String, body: String) -> Tweet {
+        let subject = Subject::parse(&body);
+        let mentions = Mention::find_all(&body);
+        let tags = find_tags(&body).map(String::from).collect();
+
+        Tweet {
+            timestamp,
+            raw_timestamp,
+            body,
+            subject,
+            mentions,
+            tags,
+        }
     }
 
-    /// Returns the body of the tweet.
+    /// Returns the timestamp for a given tweet, with the UTC offset
+    /// the author used.
+    pub fn timestamp(&self) -> DateTime<FixedOffset> {
+        self.timestamp
+    }
+
+    /// Returns the timestamp exactly as it was written in the
+    /// `twtxt.txt` file.
+    pub fn raw_timestamp(&self) -> &str {
+        &self.raw_timestamp
+    }
+
+    /// Returns the twt hash identifying this tweet, as used by `yarn.social`
+    /// and `jenny` for replies. `feed_url` should be the URL of the feed the
+    /// tweet belongs to; see `Twtxt::hash_url()`.
+    pub fn hash(&self, feed_url: &str) -> String {
+        parse::hash(feed_url, &self.timestamp, &self.body)
+    }
+
+    /// Returns the body of the tweet as it was written in the `twtxt.txt`
+    /// file, with multiple lines separated by `U+2028`.
     pub fn body(&self) -> &str {
         &self.body
     }
 
+    /// Returns the body of the tweet with `U+2028` line separators
+    /// decoded into newlines.
+    ///
+    /// # Examples
+    /// ```
+    /// # use std::str::FromStr;
+    /// # use rustwtxt::Tweet;
+    /// let tweet = Tweet::from_str("2020-01-01T00:00:00Z\tfirst\u{2028}second").unwrap();
+    /// assert_eq!("first\nsecond", tweet.text());
+    /// ```
+    pub fn text(&self) -> String {
+        self.body.replace(LINE_SEPARATOR, "\n")
+    }
+
+    /// Returns each line of a multi-line tweet.
+    pub fn lines(&self) -> Vec<&str> {
+        self.body.split(LINE_SEPARATOR).collect()
+    }
+
+    /// Splits the body of the tweet into text, mentions, tags, links,
+    /// images, code and the subject, in the order they appear.
+    pub fn tokens(&self) -> Vec<Token<'_>> {
+        token::tokenize(&self.body)
+    }
+
+    /// The subject the tweet was written under, such as the twt hash
+    /// of the tweet it replies to.
+    pub fn subject(&self) -> Option<&Subject> {
+        self.subject.as_ref()
+    }
+
     /// Any mentions within the body of the tweet have been parsed out
     /// and are retrievable through this method.
-    pub fn mentions(&self) -> Vec<String> {
-        self.mentions.clone()
+    pub fn mentions(&self) -> &[Mention] {
+        &self.mentions
     }
 
     /// Any tags within the body of the tweet have been parsed out
     /// and are retrievable through this method.
-    pub fn tags(&self) -> Vec<String> {
-        self.tags.clone()
+    pub fn tags(&self) -> &[String] {
+        &self.tags
     }
 }
 
 impl std::str::FromStr for Tweet {
-    type Err = Box<dyn std::error::Error>;
+    type Err = Error;
 
     /// Takes a properly-formatted `twtxt` tweet and parses it
     /// into a `Tweet` structure.
     fn from_str(tweet: &str) -> Result<Tweet> {
-        let split = tweet.split('\t').collect::<Vec<&str>>();
-        let timestamp = split[0].to_string();
-        let body = split[1].to_string();
+        TweetRef::parse(tweet).map(|tweet| tweet.to_owned())
+    }
+}
 
-        let mentions_regex = Regex::new(r"[@<].*[>]+")?;
-        let tags_regex = Regex::new(r"(^|\s)#[^\s]+")?;
+impl fmt::Display for Tweet {
+    /// Formats the tweet as a line of a `twtxt.txt` file.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}\t{}", self.raw_timestamp, self.body)
+    }
+}
 
-        let mentions = mentions_regex
-            .find_iter(&body)
-            .map(|ding| ding.as_str().to_string())
-            .collect::<Vec<String>>();
+/// A tweet borrowed from the buffer it was parsed from. Only the
+/// timestamp is parsed up front; mentions, tags and the subject are found
+/// when asked for, so tweets can be scanned without allocating.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct TweetRef<'a> {
+    timestamp: DateTime<FixedOffset>,
+    raw_timestamp: &'a str,
+    body: &'a str,
+}
 
-        let tags = tags_regex
-            .find_iter(&body)
-            .map(|ding| {
-                let tmp = ding.as_str();
-                let tmp = tmp.split(' ').collect::<Vec<&str>>();
-                if tmp[0] == "" && tmp.len() > 1 {
-                    return tmp[1].to_string();
-                }
-                tmp[0].to_string()
-            })
-            .collect::<Vec<String>>();
+impl<'a> TweetRef<'a> {
+    /// Parses a single line of a `twtxt.txt` file.
+    ///
+    /// # Examples
+    /// ```
+    /// # use rustwtxt::TweetRef;
+    /// let line = "2020-01-01T00:00:00Z\thello #world";
+    /// let tweet = TweetRef::parse(line).unwrap();
+    /// assert_eq!("hello #world", tweet.body());
+    /// assert_eq!(vec!["#world"], tweet.tags().collect::<Vec<&str>>());
+    /// ```
+    pub fn parse(line: &'a str) -> Result<TweetRef<'a>> {
+        let mut split = line.splitn(2, '\t');
+        let (raw_timestamp, body) = match (split.next(), split.next()) {
+            (Some(raw_timestamp), Some(body)) => (raw_timestamp, body),
+            _ => {
+                return Err(Error::MalformedLine {
+                    line: 1,
+                    content: line.into(),
+                    problem: Problem::MissingTab,
+                })
+            }
+        };
+        let timestamp = parse::timestamp(raw_timestamp)?;
 
-        Ok(Tweet {
+        Ok(TweetRef {
             timestamp,
+            raw_timestamp,
             body,
-            mentions,
-            tags,
         })
     }
+
+    /// Every tweet in the contents of a `twtxt.txt` file, in the order
+    /// they appear. Comments and lines that can't be parsed are skipped.
+    pub fn all(twtxt: &'a str) -> impl Iterator<Item = TweetRef<'a>> {
+        twtxt
+            .lines()
+            .filter(|line| !line.starts_with('#'))
+            .filter_map(|line| TweetRef::parse(line).ok())
+    }
+
+    /// Returns the timestamp, with the UTC offset the author used.
+    pub fn timestamp(&self) -> DateTime<FixedOffset> {
+        self.timestamp
+    }
+
+    /// Returns the timestamp exactly as it was written.
+    pub fn raw_timestamp(&self) -> &'a str {
+        self.raw_timestamp
+    }
+
+    /// Returns the body of the tweet, with multiple lines separated
+    /// by `U+2028`.
+    pub fn body(&self) -> &'a str {
+        self.body
+    }
+
+    /// Returns the twt hash identifying this tweet. See `Tweet::hash()`.
+    pub fn hash(&self, feed_url: &str) -> String {
+        parse::hash(feed_url, &self.timestamp, self.body)
+    }
+
+    /// Splits the body into tokens. See `Tweet::tokens()`.
+    pub fn tokens(&self) -> Vec<Token<'a>> {
+        token::tokenize(self.body)
+    }
+
+    /// Parses the subject from the start of the body.
+    pub fn subject(&self) -> Option<Subject> {
+        Subject::parse(self.body)
+    }
+
+    /// Parses the mentions out of the body.
+    pub fn mentions(&self) -> Vec<Mention> {
+        Mention::find_all(self.body)
+    }
+
+    /// The tags within the body, including the `#`.
+    pub fn tags(&self) -> impl Iterator<Item = &'a str> {
+        find_tags(self.body)
+    }
+
+    /// Copies the tweet out of the buffer it borrows from.
+    pub fn to_owned(self) -> Tweet {
+        Tweet::with_body(self.timestamp, self.raw_timestamp.into(), self.body.into())
+    }
+}
+
+impl fmt::Display for TweetRef<'_> {
+    /// Formats the tweet as a line of a `twtxt.txt` file.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}\t{}", self.raw_timestamp, self.body)
+    }
+}
+
+pub(crate) fn find_tags(body: &str) -> impl Iterator<Item = &str> {
+    TAGS_REGEX
+        .find_iter(body)
+        .map(|tag| tag.as_str().trim_start())
 }
 
 /// Pulls the target twtxt.txt file from the specified URL.
@@ -174,22 +721,31 @@ impl std::str::FromStr for Tweet {
 ///           };
 /// ```
 pub fn pull_twtxt(url: &str) -> Result<String> {
-    let resp = ureq::get(&url).timeout_connect(5000).call();
-    if resp.error() {
-        return Err(Box::new(failure::Error::compat(format_err!(
-            "{} :: {}",
-            resp.status(),
-            &url
-        ))));
-    }
-
-    if let Ok(val) = resp.into_string() {
-        return Ok(val);
-    }
-    Err(Box::new(failure::Error::compat(format_err!(
-        "{} :: Internal Error",
-        &url
-    ))))
+    pull_with(&Registry::default(), url)
+}
+
+fn pull_with<F: Fetcher + ?Sized>(fetcher: &F, url: &str) -> Result<String> {
+    match fetcher.fetch(url, None)? {
+        FetchOutcome::Modified(fetched) => Ok(fetched.body),
+        FetchOutcome::NotModified => Err(Error::Http {
+            url: url.into(),
+            status: 304,
+        }),
+    }
+}
+
+// Files parsed offline are kept whole, however long their lines, so they
+// can be written back exactly. Only fetched feeds are held to a line cap.
+fn uncapped<R: BufRead>(reader: R) -> Parser<R> {
+    Parser::new(reader).with_max_line_length(usize::MAX)
+}
+
+// Archives may move between `http` and `https`, but not to other schemes.
+fn web_scheme(scheme: &str) -> &str {
+    match scheme {
+        "https" => "http",
+        other => other,
+    }
 }
 
 /// Wrapper to apply a function to each line of a `twtxt.txt` file,
@@ -207,16 +763,14 @@ pub fn pull_twtxt(url: &str) -> Result<String> {
 /// assert_eq!("TEST", output[0]);
 /// ```
 pub fn mutate(twtxt: &str, f: fn(&str) -> String) -> Vec<String> {
-    twtxt
-        .to_owned()
-        .lines()
-        .map(|line| f(line))
-        .collect::<Vec<String>>()
+    twtxt.to_owned().lines().map(f).collect::<Vec<String>>()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_server::{response, serve, serve_files};
+    use std::str::FromStr;
 
     const TEST_URL: &str = "https://gbmor.dev/twtxt.txt";
 
@@ -229,7 +783,7 @@ mod tests {
 
         let (_, tweet) = twtxt.tweets().iter().next().unwrap();
         assert!(tweet.body().len() > 1);
-        assert!(tweet.timestamp().len() > 1);
+        assert!(tweet.raw_timestamp().len() > 1);
         assert!(tweet.tags().is_empty());
     }
 
@@ -242,17 +796,435 @@ mod tests {
     #[test]
     fn make_twtxt() {
         let rhs = Twtxt::from(TEST_URL).unwrap();
-        let tweets = BTreeMap::new();
+        let tweets = TweetMap::new();
         let lhs = Twtxt {
             nickname: String::from("gbmor"),
             url: String::from("https://gbmor.dev/twtxt.txt"),
+            metadata: Metadata::default(),
             tweets,
+            lines: Vec::new(),
+            report: ParseReport::default(),
         };
         assert_eq!(lhs.nickname, rhs.nickname);
         assert_eq!(lhs.url, rhs.url);
         assert!(rhs.tweets.len() > 1);
     }
 
+    #[test]
+    fn parse_offline() {
+        let contents = "# nick = foo\n# url = https://example.com/twtxt.txt\n\n\
+                        2020-01-01T00:00:00Z\tfirst\n\
+                        2020-01-02T00:00:00Z\tsecond\n";
+        let twtxt = Twtxt::parse(contents, TEST_URL).unwrap();
+        assert_eq!("foo", twtxt.nick());
+        assert_eq!(TEST_URL, twtxt.url());
+        assert_eq!(2, twtxt.tweets().len());
+
+        let from_reader = Twtxt::from_reader(contents.as_bytes(), TEST_URL).unwrap();
+        assert_eq!(twtxt, from_reader);
+
+        let path = std::env::temp_dir().join("rustwtxt_parse_offline.txt");
+        std::fs::write(&path, contents).unwrap();
+        let from_path = Twtxt::from_path(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(twtxt.tweets(), from_path.tweets());
+        assert_eq!(path.to_string_lossy(), from_path.url());
+    }
+
+    #[test]
+    fn round_trip() {
+        let files = [
+            "# == Metadata ==\n# nick = foo\n#\n\n\
+             2020-01-01T00:00:00Z\tfirst\twith a tab\n\
+             # a comment in the middle\n\
+             not a tweet\n\
+             yesterday\tbad timestamp\n\n\n\
+             2020-01-02T00:00:00+02:00\tsecond @<bar https://bar.example.com> #tag\n",
+            "# nick = foo\n2020-01-01T00:00:00Z\tno trailing newline",
+            "# nick = foo\r\n\r\n2020-01-01T00:00:00Z\tcrlf\r\n",
+            "# nick = foo\r\n2020-01-01T00:00:00Z\tmixed\n\r\n# done\r",
+            "# nick = foo\n2020-01-01T00:00:00Z\tone\n2020-01-01T00:00:00Z\ttwo\n",
+        ];
+        for file in files.iter() {
+            let twtxt = Twtxt::parse(file, TEST_URL).unwrap();
+            assert_eq!(*file, twtxt.to_string());
+
+            let mut out = Vec::new();
+            twtxt.write_to(&mut out).unwrap();
+            assert_eq!(file.as_bytes(), &out[..]);
+        }
+
+        // Longer than any fetched line may be.
+        let long = format!(
+            "# nick = foo\n2020-01-01T00:00:00Z\t{}\n",
+            "é".repeat(FetchOptions::default().max_line_length)
+        );
+        assert_eq!(long, Twtxt::parse(&long, TEST_URL).unwrap().to_string());
+    }
+
+    #[test]
+    fn parse_report() {
+        let contents = "# nick = foo\n\
+                        2020-01-01T00:00:00Z\tfine\n\
+                        no tab\n\
+                        yesterday\tbad timestamp\n\
+                        2020-01-01T00:00:00Z\tsame second\n\
+                        2020-01-02T00:00:00Z\tbell \u{7}\n";
+        let twtxt = Twtxt::parse(contents, TEST_URL).unwrap();
+        let problems = twtxt
+            .report()
+            .diagnostics()
+            .iter()
+            .map(|diagnostic| (diagnostic.line, diagnostic.problem))
+            .collect::<Vec<(usize, Problem)>>();
+        assert_eq!(
+            vec![
+                (3, Problem::MissingTab),
+                (4, Problem::BadTimestamp),
+                (5, Problem::DuplicateTimestamp),
+                (6, Problem::ControlCharacters),
+            ],
+            problems
+        );
+        assert_eq!(2, twtxt.report().skipped().count());
+        assert_eq!(3, twtxt.tweets().len());
+        assert_eq!(contents, twtxt.to_string());
+
+        match Twtxt::parse_with(contents, TEST_URL, &ParseOptions::strict()) {
+            Err(Error::MalformedLine {
+                line,
+                content,
+                problem,
+            }) => {
+                assert_eq!(3, line);
+                assert_eq!("no tab", content);
+                assert_eq!(Problem::MissingTab, problem);
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        let clean = Twtxt::parse("# nick = foo\n\n2020-01-01T00:00:00Z\tfine\n", TEST_URL);
+        assert!(clean.unwrap().report().is_clean());
+    }
+
+    #[test]
+    fn push_tweet() {
+        let mut twtxt =
+            Twtxt::parse("# nick = foo\n2020-01-01T00:00:00Z\tfirst\n", TEST_URL).unwrap();
+        let tweet = Tweet::from_str("2020-01-02T00:00:00Z\tsecond").unwrap();
+        twtxt.push(tweet);
+        assert_eq!(
+            "# nick = foo\n2020-01-01T00:00:00Z\tfirst\n2020-01-02T00:00:00Z\tsecond\n",
+            twtxt.to_string()
+        );
+        assert_eq!(2, twtxt.tweets().len());
+
+        twtxt.push(Tweet::from_str("2020-01-02T00:00:00Z\tagain").unwrap());
+        let bodies = twtxt
+            .tweets_at("2020-01-02T01:00:00+01:00")
+            .map(Tweet::body)
+            .collect::<Vec<&str>>();
+        assert_eq!(vec!["second", "again"], bodies);
+    }
+
+    #[test]
+    fn same_second_tweets() {
+        let contents = "# nick = foo\n\
+                        2020-01-01T00:00:00Z\tone\n\
+                        2020-01-01T01:00:00+01:00\ttwo\n\
+                        2019-12-31T00:00:00Z\tearlier\n";
+        let twtxt = Twtxt::parse(contents, TEST_URL).unwrap();
+        let bodies = twtxt
+            .tweets()
+            .values()
+            .map(Tweet::body)
+            .collect::<Vec<&str>>();
+        assert_eq!(vec!["earlier", "one", "two"], bodies);
+        assert_eq!("one", twtxt.tweet("2020-01-01T00:00:00Z").unwrap().body());
+        assert_eq!(1, twtxt.report().diagnostics().len());
+        assert_eq!(contents, twtxt.to_string());
+    }
+
+    #[test]
+    fn keep_newest() {
+        let contents = "# nick = foo\n\
+                        2020-01-03T00:00:00Z\tthree\n\
+                        2020-01-01T00:00:00Z\tone\n\
+                        2020-01-02T00:00:00Z\ttwo\n";
+        let mut twtxt = Twtxt::parse(contents, TEST_URL).unwrap();
+        twtxt.keep_newest(2);
+
+        let bodies = twtxt
+            .tweets()
+            .values()
+            .map(|t| t.body())
+            .collect::<Vec<&str>>();
+        assert_eq!(vec!["two", "three"], bodies);
+        assert_eq!(
+            "# nick = foo\n2020-01-03T00:00:00Z\tthree\n2020-01-02T00:00:00Z\ttwo\n",
+            twtxt.to_string()
+        );
+
+        let contents = "# nick = foo\n\
+                        2020-01-01T00:00:00Z\tone\n\
+                        2020-01-02T00:00:00Z\ttwo\n\
+                        2020-01-03T00:00:00Z\tthree\n";
+        let mut twtxt = Twtxt::parse(contents, TEST_URL).unwrap();
+        twtxt.keep_newest(1);
+        twtxt.push(Tweet::from_str("2020-01-03T00:00:00Z\tagain").unwrap());
+        twtxt.push(Tweet::from_str("2020-01-03T00:00:00Z\tonce more").unwrap());
+        let bodies = twtxt
+            .tweets()
+            .values()
+            .map(|t| t.body())
+            .collect::<Vec<&str>>();
+        assert_eq!(vec!["three", "again", "once more"], bodies);
+        assert_eq!(4, twtxt.to_string().lines().count());
+    }
+
+    #[test]
+    fn parse_without_nick() {
+        match Twtxt::parse("2020-01-01T00:00:00Z\thello", TEST_URL) {
+            Err(Error::MissingMetadata(key)) => assert_eq!("nick", key),
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        let contents: &[u8] = b"2020-01-01T00:00:00Z\thello\n";
+        let twtxt = Twtxt::from_reader_with_nick(contents, TEST_URL, "foo").unwrap();
+        assert_eq!("foo", twtxt.nick());
+        let contents: &[u8] = b"# nick = bar\n";
+        let twtxt = Twtxt::from_reader_with_nick(contents, TEST_URL, "foo").unwrap();
+        assert_eq!("bar", twtxt.nick());
+    }
+
+    #[test]
+    fn tweets_sort_by_instant() {
+        let early = Tweet::from_str("2020-01-01T10:00:00+02:00\tearly").unwrap();
+        let late = Tweet::from_str("2020-01-01T09:00:00Z\tlate").unwrap();
+        assert_eq!("2020-01-01T10:00:00+02:00", early.raw_timestamp());
+
+        let mut tweets = TweetMap::new();
+        tweets.insert((late.timestamp(), 1), late);
+        tweets.insert((early.timestamp(), 2), early);
+
+        let bodies = tweets.values().map(|t| t.body()).collect::<Vec<&str>>();
+        assert_eq!(vec!["early", "late"], bodies);
+    }
+
+    #[test]
+    fn tweet_mentions() {
+        let tweet = Tweet::from_str(
+            "2020-01-01T00:00:00Z\t@<foo https://foo.example.com/twtxt.txt> meet @<bar https://bar.example.com/twtxt.txt>",
+        )
+        .unwrap();
+        let nicks = tweet
+            .mentions()
+            .iter()
+            .map(|mention| mention.nick.clone().unwrap())
+            .collect::<Vec<String>>();
+        assert_eq!(vec!["foo", "bar"], nicks);
+    }
+
+    #[test]
+    fn borrowed_tweets() {
+        let contents = "# nick = foo\n2020-01-01T00:00:00Z\t(#abcdefg) #one\u{2028}#two\n\
+                        bad line\n2020-01-02T00:00:00+01:00\t@<bar https://bar.example.com/twtxt.txt>\n";
+        let tweets = TweetRef::all(contents).collect::<Vec<TweetRef>>();
+        assert_eq!(2, tweets.len());
+        assert_eq!(
+            vec!["#one", "#two"],
+            tweets[0].tags().collect::<Vec<&str>>()
+        );
+        assert_eq!(
+            Some("abcdefg"),
+            tweets[0].subject().as_ref().and_then(Subject::hash)
+        );
+        assert_eq!("2020-01-02T00:00:00+01:00", tweets[1].raw_timestamp());
+        assert_eq!(1, tweets[1].mentions().len());
+
+        let owned = tweets[0].to_owned();
+        assert_eq!(tweets[0].to_string(), owned.to_string());
+        assert_eq!(&["#one", "#two"], owned.tags());
+        assert_eq!(tweets[0].hash(TEST_URL), owned.hash(TEST_URL));
+    }
+
+    #[test]
+    fn tweet_hashes() {
+        let contents = "# nick = foo\n# url = https://example.com/twtxt.txt\n\
+                        2016-02-04T13:30:00Z\tYou can really go crazy here! ┐(ﾟ∀ﾟ)┌\n";
+        let twtxt = Twtxt::parse(contents, "https://mirror.example.com/twtxt.txt").unwrap();
+        assert_eq!("https://example.com/twtxt.txt", twtxt.hash_url());
+
+        let tweet = twtxt.tweet("2016-02-04T13:30:00Z").unwrap();
+        assert_eq!("5gg4u4a", twtxt.tweet_hash(tweet));
+        assert_eq!(Some(tweet), twtxt.tweet_by_hash("5gg4u4a"));
+        assert_eq!(None, twtxt.tweet_by_hash("aaaaaaa"));
+    }
+
+    #[test]
+    fn multiline_tweets() {
+        let timestamp = parse::timestamp("2020-01-01T00:00:00Z").unwrap();
+        let tweet = Tweet::new(timestamp, "first\nsecond\r\n\nfourth");
+        assert_eq!("first\u{2028}second\u{2028}\u{2028}fourth", tweet.body());
+        assert_eq!(vec!["first", "second", "", "fourth"], tweet.lines());
+        assert_eq!("first\nsecond\n\nfourth", tweet.text());
+        assert!(!tweet.to_string().contains('\n'));
+
+        let mut twtxt = Twtxt::parse("# nick = foo\n", TEST_URL).unwrap();
+        twtxt.push(tweet.clone());
+        let twtxt = Twtxt::parse(&twtxt.to_string(), TEST_URL).unwrap();
+        assert_eq!(Some(&tweet), twtxt.tweet("2020-01-01T00:00:00Z"));
+    }
+
+    #[test]
+    fn bad_timestamp() {
+        match Tweet::from_str("yesterday\tsome tweet") {
+            Err(Error::Timestamp(raw)) => assert_eq!("yesterday", raw),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        match Tweet::from_str("no tab here") {
+            Err(Error::MalformedLine {
+                line,
+                content,
+                problem,
+            }) => {
+                assert_eq!(1, line);
+                assert_eq!("no tab here", content);
+                assert_eq!(Problem::MissingTab, problem);
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn http_errors() {
+        let (url, _) = serve(vec![response("404 Not Found", "", "")]);
+        match Twtxt::from(&url) {
+            Err(Error::Http { status, .. }) => assert_eq!(404, status),
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        let (url, _) = serve(vec![response("200 OK", "", "hello")]);
+        assert!(matches!(Twtxt::from(&url), Err(Error::MissingMetadata(_))));
+    }
+
+    #[test]
+    fn feed_history() {
+        let url = serve_files(&[
+            (
+                "/twtxt.txt",
+                "# nick = foo\n# prev = aaaaaaa archive/2.txt\n2020-03-01T00:00:00Z\tthree\n",
+            ),
+            (
+                "/archive/2.txt",
+                "# prev = bbbbbbb 1.txt\n2020-02-01T00:00:00Z\ttwo\n",
+            ),
+            (
+                "/archive/1.txt",
+                "# prev = ../twtxt.txt\n2020-01-01T00:00:00Z\tone\n2020-02-01T00:00:00Z\ttwo\n",
+            ),
+        ]);
+        let url = format!("{}/twtxt.txt", url);
+        let twtxt = Twtxt::from(&url).unwrap();
+
+        let bodies = |twtxt: &Twtxt| {
+            twtxt
+                .tweets()
+                .values()
+                .map(|tweet| tweet.body().to_string())
+                .collect::<Vec<String>>()
+        };
+        let (history, error) = twtxt.fetch_history(10);
+        assert!(error.is_none());
+        assert_eq!(vec!["one", "two", "three"], bodies(&history));
+        assert_eq!("foo", history.nick());
+
+        let (history, _) = twtxt.fetch_history(1);
+        assert_eq!(vec!["two", "three"], bodies(&history));
+        assert_eq!(bodies(&twtxt), bodies(&twtxt.fetch_history(0).0));
+    }
+
+    #[test]
+    fn history_keeps_scheme() {
+        let path = std::env::temp_dir().join("rustwtxt-history-secret.txt");
+        std::fs::write(&path, "2020-01-01T00:00:00Z\tsecret\n").unwrap();
+        let prev = url::Url::from_file_path(&path).unwrap();
+
+        let mut files = fetch::MemoryFetcher::new();
+        files.insert(
+            "https://example.com/twtxt.txt",
+            &format!(
+                "# nick = foo\n# prev = {}\n2020-02-01T00:00:00Z\tnew\n",
+                prev
+            ),
+        );
+        let mut registry = Registry::default();
+        registry.register("https", files);
+
+        let twtxt = Twtxt::from_with(&registry, "https://example.com/twtxt.txt").unwrap();
+        let (history, error) = twtxt.fetch_history_with(&registry, 10);
+        assert_eq!(1, history.tweets().len());
+        match error {
+            Some(Error::Transport { url, .. }) => assert_eq!(prev.as_str(), url),
+            other => panic!("unexpected error: {:?}", other),
+        }
+        std::fs::remove_file(path).unwrap();
+
+        // An archive with a bad link of its own is still merged.
+        let mut files = fetch::MemoryFetcher::new();
+        files
+            .insert(
+                "https://example.com/twtxt.txt",
+                "# nick = foo\n# prev = a.txt\n2020-02-01T00:00:00Z\tnew\n",
+            )
+            .insert(
+                "https://example.com/a.txt",
+                "# prev = gopher://evil/0/x\n2020-01-01T00:00:00Z\told\n",
+            );
+        let twtxt = Twtxt::from_with(&files, "https://example.com/twtxt.txt").unwrap();
+        let (history, error) = twtxt.fetch_history_with(&files, 10);
+        assert_eq!(2, history.tweets().len());
+        match error {
+            Some(Error::Transport { url, .. }) => assert_eq!("gopher://evil/0/x", url),
+            other => panic!("unexpected error: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn mock_fetcher() {
+        let mut files = fetch::MemoryFetcher::new();
+        files
+            .insert(
+                "https://example.com/twtxt.txt",
+                "# nick = foo\n# prev = old.txt\n2020-02-01T00:00:00Z\tnew\n",
+            )
+            .insert(
+                "https://example.com/old.txt",
+                "# prev = gone.txt\n2020-01-01T00:00:00Z\told\n2020-02-01T00:00:00Z\tnew\n",
+            );
+
+        let twtxt = Twtxt::from_with(&files, "https://example.com/twtxt.txt").unwrap();
+        assert_eq!(1, twtxt.tweets().len());
+        let (history, error) = twtxt.fetch_history_with(&files, 1);
+        assert_eq!(2, history.tweets().len());
+        assert!(error.is_none());
+
+        let (history, error) = twtxt.fetch_history_with(&files, 2);
+        assert_eq!(2, history.tweets().len());
+        match error {
+            Some(Error::Http { url, status }) => {
+                assert_eq!(("https://example.com/gone.txt", 404), (&*url, status));
+            }
+            other => panic!("unexpected error: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn missing_file() {
+        let err = Twtxt::from_path("/this/file/does/not/exist.txt").unwrap_err();
+        assert!(matches!(err, Error::Io(_)));
+    }
+
     #[test]
     fn test_mutate() {
         let input = "test";
@@ -0,0 +1,104 @@
+//! Mentions of other feeds within the body of a tweet.
+
+use std::ops::Range;
+
+use lazy_static::lazy_static;
+use regex::{Captures, Regex};
+use url::Url;
+
+lazy_static! {
+    // Matches `@<nick url>` and `@<url>`, one mention at a time.
+    pub(crate) static ref MENTION_REGEX: Regex =
+        Regex::new(r"@<(?:([^\s<>]+)\s+)?([^\s<>]+)>").unwrap();
+}
+
+/// A mention of another feed, written as `@<nick url>` or `@<url>`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Mention {
+    /// The nickname, if one was given.
+    pub nick: Option<String>,
+    /// The URL of the mentioned feed.
+    pub url: Url,
+    /// Byte range of the whole mention within the tweet body.
+    pub span: Range<usize>,
+}
+
+impl Mention {
+    /// Finds every mention in the body of a tweet, in order. Mentions
+    /// without a valid URL are skipped.
+    ///
+    /// # Examples
+    /// ```
+    /// # use rustwtxt::Mention;
+    /// let body = "hi @<foo https://foo.example.com/twtxt.txt> and @<https://bar.example.com/twtxt.txt>";
+    /// let mentions = Mention::find_all(body);
+    /// assert_eq!(2, mentions.len());
+    /// assert_eq!(Some("foo"), mentions[0].nick.as_deref());
+    /// assert_eq!(None, mentions[1].nick);
+    /// assert_eq!("@<https://bar.example.com/twtxt.txt>", &body[mentions[1].span.clone()]);
+    /// ```
+    pub fn find_all(body: &str) -> Vec<Mention> {
+        MENTION_REGEX
+            .captures_iter(body)
+            .filter_map(|caps| Mention::from_captures(&caps, 0))
+            .collect()
+    }
+
+    // The mention `text` starts with, if any. `offset` is the position
+    // of `text` within the tweet body.
+    pub(crate) fn at_start(text: &str, offset: usize) -> Option<Mention> {
+        let caps = MENTION_REGEX.captures(text)?;
+        if caps.get(0)?.start() != 0 {
+            return None;
+        }
+        Mention::from_captures(&caps, offset)
+    }
+
+    fn from_captures(caps: &Captures, offset: usize) -> Option<Mention> {
+        let whole = caps.get(0)?;
+        let url = Url::parse(caps.get(2)?.as_str()).ok()?;
+        Some(Mention {
+            nick: caps.get(1).map(|nick| nick.as_str().to_string()),
+            url,
+            span: offset + whole.start()..offset + whole.end(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn two_mentions_are_not_merged() {
+        let body = "@<foo https://foo.example.com/twtxt.txt> said hi to @<bar https://bar.example.com/twtxt.txt>!";
+        let mentions = Mention::find_all(body);
+        assert_eq!(2, mentions.len());
+        assert_eq!(Some("foo".to_string()), mentions[0].nick);
+        assert_eq!(
+            "https://foo.example.com/twtxt.txt",
+            mentions[0].url.as_str()
+        );
+        assert_eq!(0..40, mentions[0].span);
+        assert_eq!(Some("bar".to_string()), mentions[1].nick);
+        assert_eq!(
+            "@<bar https://bar.example.com/twtxt.txt>",
+            &body[mentions[1].span.clone()]
+        );
+    }
+
+    #[test]
+    fn url_only_mention() {
+        let mentions = Mention::find_all("ping @<https://foo.example.com/twtxt.txt>");
+        assert_eq!(1, mentions.len());
+        assert_eq!(None, mentions[0].nick);
+        assert_eq!(5..41, mentions[0].span);
+    }
+
+    #[test]
+    fn invalid_mentions() {
+        assert!(Mention::find_all("@<foo not-a-url>").is_empty());
+        assert!(Mention::find_all("a <b> c").is_empty());
+        assert!(Mention::find_all("@<foo").is_empty());
+    }
+}
@@ -0,0 +1,228 @@
+//! Structured access to the `== Metadata ==` section of a `twtxt.txt` file.
+
+use std::collections::BTreeMap;
+
+use crate::parse;
+
+/// Metadata declared in the comment header of a `twtxt.txt` file, in the
+/// form `# key = value`. Keys that may appear more than once, such as
+/// `url` and `follow`, keep every value in the order they were declared.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Metadata {
+    nick: Option<String>,
+    urls: Vec<String>,
+    follows: Vec<Follow>,
+    links: Vec<Link>,
+    avatar: Option<String>,
+    description: Option<String>,
+    prev: Option<Prev>,
+    refresh: Option<u64>,
+    other: BTreeMap<String, Vec<String>>,
+}
+
+/// A `# follow = nick url` entry.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Follow {
+    pub nick: String,
+    pub url: String,
+}
+
+/// A `# link = text url` entry. The text may contain spaces.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Link {
+    pub text: String,
+    pub url: String,
+}
+
+/// A `# prev = hash file` entry pointing to an archived feed.
+/// The hash is optional.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Prev {
+    pub hash: Option<String>,
+    pub file: String,
+}
+
+impl Metadata {
+    /// Parses the metadata out of the comment lines of a `twtxt.txt` file.
+    /// Tweet bodies are never considered.
+    ///
+    /// # Examples
+    /// ```
+    /// # use rustwtxt::Metadata;
+    /// let twtxt = "# nick = foo\n# url = https://example.com/twtxt.txt\n";
+    /// let meta = Metadata::parse(twtxt);
+    /// assert_eq!(Some("foo"), meta.nick());
+    /// assert_eq!("https://example.com/twtxt.txt", meta.urls()[0]);
+    /// ```
+    pub fn parse(twtxt: &str) -> Metadata {
+        let mut meta = Metadata::default();
+        twtxt
+            .lines()
+            .filter_map(parse::metadata_field)
+            .for_each(|(key, value)| meta.insert(key, value));
+        meta
+    }
+
+    pub(crate) fn insert(&mut self, key: &str, value: &str) {
+        match &*key.to_lowercase() {
+            "nick" if self.nick.is_none() => self.nick = Some(value.into()),
+            "url" => self.urls.push(value.into()),
+            "follow" => {
+                if let Some((nick, url)) = split_last_word(value) {
+                    self.follows.push(Follow {
+                        nick: nick.into(),
+                        url: url.into(),
+                    });
+                }
+            }
+            "link" => {
+                if let Some((text, url)) = split_last_word(value) {
+                    self.links.push(Link {
+                        text: text.into(),
+                        url: url.into(),
+                    });
+                }
+            }
+            "avatar" if self.avatar.is_none() => self.avatar = Some(value.into()),
+            "description" if self.description.is_none() => self.description = Some(value.into()),
+            "prev" if self.prev.is_none() => {
+                self.prev = match split_last_word(value) {
+                    Some((hash, file)) => Some(Prev {
+                        hash: Some(hash.into()),
+                        file: file.into(),
+                    }),
+                    None => Some(Prev {
+                        hash: None,
+                        file: value.into(),
+                    }),
+                }
+            }
+            "refresh" if self.refresh.is_none() => self.refresh = value.parse().ok(),
+            "nick" | "avatar" | "description" | "prev" | "refresh" => {}
+            other => self
+                .other
+                .entry(other.into())
+                .or_default()
+                .push(value.into()),
+        }
+    }
+
+    /// The author's nickname.
+    pub fn nick(&self) -> Option<&str> {
+        self.nick.as_deref()
+    }
+
+    /// Every URL the feed is published under, the canonical one first.
+    pub fn urls(&self) -> &[String] {
+        &self.urls
+    }
+
+    /// Feeds the author follows.
+    pub fn follows(&self) -> &[Follow] {
+        &self.follows
+    }
+
+    /// Links the author has attached to their feed.
+    pub fn links(&self) -> &[Link] {
+        &self.links
+    }
+
+    /// URL of the author's avatar.
+    pub fn avatar(&self) -> Option<&str> {
+        self.avatar.as_deref()
+    }
+
+    /// Free-form description of the feed.
+    pub fn description(&self) -> Option<&str> {
+        self.description.as_deref()
+    }
+
+    /// The previous archived feed, if the feed has been rotated.
+    pub fn prev(&self) -> Option<&Prev> {
+        self.prev.as_ref()
+    }
+
+    /// Suggested refresh interval, in seconds.
+    pub fn refresh(&self) -> Option<u64> {
+        self.refresh
+    }
+
+    /// Any keys not covered by the other accessors, lowercased, with
+    /// every value they were given.
+    pub fn other(&self) -> &BTreeMap<String, Vec<String>> {
+        &self.other
+    }
+}
+
+// Splits "some words last" into ("some words", "last").
+fn split_last_word(value: &str) -> Option<(&str, &str)> {
+    let idx = value.rfind(char::is_whitespace)?;
+    let (head, tail) = (value[..idx].trim(), value[idx..].trim());
+    if head.is_empty() || tail.is_empty() {
+        return None;
+    }
+    Some((head, tail))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TWTXT: &str = "# == Metadata ==
+#
+# nick        = foo
+# url         = https://example.com/twtxt.txt
+# url         = https://mirror.example.com/twtxt.txt
+# follow      = bar https://bar.example.com/twtxt.txt
+# follow      = baz https://baz.example.com/twtxt.txt
+# link        = My Website https://example.com
+# avatar      = https://example.com/avatar.png
+# description = Just a test feed
+# prev        = abcdefg twtxt-2020-01-01.txt
+# refresh     = 3600
+# kind        = bot
+#
+2020-01-01T00:00:00Z\tthis tweet has = in it
+";
+
+    #[test]
+    fn parse_all_fields() {
+        let meta = Metadata::parse(TWTXT);
+        assert_eq!(Some("foo"), meta.nick());
+        assert_eq!(2, meta.urls().len());
+        assert_eq!("https://example.com/twtxt.txt", meta.urls()[0]);
+        assert_eq!("https://mirror.example.com/twtxt.txt", meta.urls()[1]);
+        assert_eq!(2, meta.follows().len());
+        assert_eq!("baz", meta.follows()[1].nick);
+        assert_eq!("https://baz.example.com/twtxt.txt", meta.follows()[1].url);
+        assert_eq!("My Website", meta.links()[0].text);
+        assert_eq!("https://example.com", meta.links()[0].url);
+        assert_eq!(Some("https://example.com/avatar.png"), meta.avatar());
+        assert_eq!(Some("Just a test feed"), meta.description());
+        assert_eq!(
+            Some(&Prev {
+                hash: Some("abcdefg".into()),
+                file: "twtxt-2020-01-01.txt".into()
+            }),
+            meta.prev()
+        );
+        assert_eq!(Some(3600), meta.refresh());
+        assert_eq!(vec!["bot".to_string()], meta.other()["kind"]);
+        assert!(!meta.other().contains_key("this tweet has"));
+    }
+
+    #[test]
+    fn prev_without_hash() {
+        let meta = Metadata::parse("# prev = twtxt-old.txt");
+        assert_eq!(None, meta.prev().unwrap().hash);
+        assert_eq!("twtxt-old.txt", meta.prev().unwrap().file);
+    }
+
+    #[test]
+    fn empty_metadata() {
+        assert_eq!(
+            Metadata::default(),
+            Metadata::parse("2020-01-01T00:00:00Z\thi")
+        );
+    }
+}
@@ -1,34 +1,18 @@
 //! Lower-level parsing functions for when you don't want to use
 //! the provided `Twtxt` and `Tweet` objects.
 
-use std::collections::BTreeMap;
+use blake2::digest::consts::U32;
+use blake2::{Blake2b, Digest};
+use chrono::{DateTime, FixedOffset, SecondsFormat};
+use data_encoding::BASE32_NOPAD;
 
-use regex::Regex;
-
-type TwtxtErr<T> = std::result::Result<T, ErrorKind>;
-
-#[derive(Debug)]
-pub enum ErrorKind {
-    Metadata,
-    Keyword,
-    Regex,
-}
-
-impl std::fmt::Display for ErrorKind {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let kind = match self {
-            ErrorKind::Metadata => "Metadata",
-            ErrorKind::Keyword => "Keyword",
-            ErrorKind::Regex => "Regex",
-        };
-        write!(f, "{}", kind)
-    }
-}
-
-impl std::error::Error for ErrorKind {}
+use crate::mention::MENTION_REGEX;
+use crate::{Error, Result};
 
 /// This parses out the specified information in the `== Metadata ==` section of
-/// a given `twtxt.txt` file.
+/// a given `twtxt.txt` file. Only comment lines are searched, and the first
+/// value given for the keyword is returned. Use `rustwtxt::Metadata` to get
+/// every value of a repeated keyword.
 ///
 /// # Examples
 /// ```
@@ -43,94 +27,136 @@ impl std::error::Error for ErrorKind {}
 ///
 /// let out = parse::metadata(&twtxt, "nick");
 /// ```
-pub fn metadata(twtxt: &str, keyword: &str) -> TwtxtErr<String> {
+pub fn metadata(twtxt: &str, keyword: &str) -> Result<String> {
     if !twtxt.contains("== Metadata ==") && !twtxt.contains(keyword) {
-        return Err(ErrorKind::Metadata);
+        return Err(Error::MissingMetadata(keyword.into()));
     }
 
-    let regex_string = format!("{} = (.*)", keyword);
-
-    let regex = if let Ok(val) = Regex::new(&regex_string) {
-        val
-    } else {
-        return Err(ErrorKind::Regex);
-    };
+    twtxt
+        .lines()
+        .filter_map(metadata_field)
+        .find(|(key, _)| key.eq_ignore_ascii_case(keyword))
+        .map(|(_, value)| value.to_string())
+        .ok_or_else(|| Error::MissingMetadata(keyword.into()))
+}
 
-    let matched = if let Some(val) = regex.captures(twtxt) {
-        val
-    } else {
-        return Err(ErrorKind::Keyword);
-    };
+/// Splits a metadata comment line of the form `# key = value` into its
+/// key and value. Returns `None` for anything else, including tweets.
+///
+/// # Examples
+/// ```
+/// # use rustwtxt::parse;
+/// assert_eq!(Some(("nick", "foo")), parse::metadata_field("# nick = foo"));
+/// assert_eq!(None, parse::metadata_field("# just a comment"));
+/// ```
+pub fn metadata_field(line: &str) -> Option<(&str, &str)> {
+    let line = line.strip_prefix('#')?;
+    let idx = line.find('=')?;
+    let (key, value) = (line[..idx].trim(), line[idx + 1..].trim());
+    if key.is_empty() || key.contains(char::is_whitespace) {
+        return None;
+    }
+    Some((key, value))
+}
 
-    let keyword_match = if let Some(val) = matched.get(1) {
-        val.as_str()
-    } else {
-        return Err(ErrorKind::Keyword);
-    };
+/// Parses a tweet's `RFC 3339` timestamp, keeping the author's UTC offset.
+///
+/// # Examples
+/// ```
+/// # use rustwtxt::parse;
+/// let early = parse::timestamp("2020-01-01T10:00:00+02:00").unwrap();
+/// let late = parse::timestamp("2020-01-01T09:00:00Z").unwrap();
+/// assert!(early < late);
+/// ```
+pub fn timestamp(raw: &str) -> Result<DateTime<FixedOffset>> {
+    DateTime::parse_from_rfc3339(raw.trim()).map_err(|_| Error::Timestamp(raw.into()))
+}
 
-    Ok(keyword_match.to_string())
+/// Computes the hash identifying a tweet, compatible with `yarn.social`
+/// and other clients implementing the twt hash extension: the last seven
+/// characters of the lowercase, unpadded base32 encoding of the
+/// blake2b-256 digest of `url\ntimestamp\nbody`. The timestamp is
+/// written in `RFC 3339` format to the second, using `Z` for UTC.
+///
+/// # Examples
+/// ```
+/// # use rustwtxt::parse;
+/// let timestamp = parse::timestamp("2016-02-04T13:30:00Z").unwrap();
+/// let hash = parse::hash(
+///     "https://example.com/twtxt.txt",
+///     &timestamp,
+///     "You can really go crazy here! ┐(ﾟ∀ﾟ)┌",
+/// );
+/// assert_eq!("5gg4u4a", hash);
+/// ```
+pub fn hash(url: &str, timestamp: &DateTime<FixedOffset>, body: &str) -> String {
+    let payload = format!(
+        "{}\n{}\n{}",
+        url,
+        timestamp.to_rfc3339_opts(SecondsFormat::Secs, true),
+        body
+    );
+    let digest = Blake2b::<U32>::digest(payload.as_bytes());
+    let encoded = BASE32_NOPAD.encode(&digest).to_lowercase();
+    encoded[encoded.len() - 7..].to_string()
 }
 
 /// Pull the individual tweets from a remote `twtxt.txt` file into
-/// a `std::collections::BTreeMap<String, String>`, The timestamp
-/// is the key while the status is the value.
-pub fn statuses(twtxt: &str) -> Option<BTreeMap<String, String>> {
-    let mut map = BTreeMap::new();
+/// a list of `(timestamp, status)` pairs, sorted by the moment each was
+/// posted. Tweets sharing a timestamp are all kept, in the order they
+/// appear, and those whose timestamp can't be parsed come last.
+pub fn statuses(twtxt: &str) -> Option<Vec<(String, String)>> {
+    let mut statuses = Vec::new();
     let lines = twtxt.split('\n').collect::<Vec<&str>>();
     lines.iter().for_each(|line| {
         if line.starts_with('#') || line.len() < 2 || !line.contains('\t') {
             return;
         }
 
-        let status = line.split('\t').collect::<Vec<&str>>();
-        let datestamp = status[0];
-        map.insert(datestamp.into(), status[1].into());
+        let mut status = line.splitn(2, '\t');
+        if let (Some(datestamp), Some(body)) = (status.next(), status.next()) {
+            statuses.push((datestamp.to_string(), body.to_string()));
+        }
     });
 
-    if map.is_empty() {
+    if statuses.is_empty() {
         return None;
     }
-    Some(map)
+    // Unparsable timestamps sort after every real one, in file order.
+    statuses.sort_by_cached_key(|(datestamp, _)| match timestamp(datestamp) {
+        Ok(ts) => (false, Some(ts)),
+        Err(_) => (true, None),
+    });
+    Some(statuses)
 }
 
-/// Parse the mentions out of a `twtxt.txt` file. Returns a
-/// `std::collections::BTreeMap<String, String>` with the
-/// timestamp of the tweet as the key and the mention as
-/// the associated value.
-pub fn mentions(twtxt: &str) -> Option<BTreeMap<String, String>> {
-    let statuses = if let Some(val) = statuses(&twtxt) {
-        val
-    } else {
-        return None;
-    };
-    let mut map = BTreeMap::new();
+/// Parse the mentions out of a `twtxt.txt` file. Returns a list of
+/// `(timestamp, mentions)` pairs, sorted by timestamp, with the mentions
+/// of each tweet separated by spaces.
+pub fn mentions(twtxt: &str) -> Option<Vec<(String, String)>> {
+    let statuses = statuses(twtxt)?;
+    let mut found = Vec::new();
     statuses.iter().for_each(|(k, v)| {
-        if !v.contains("@<") {
+        let mentions = MENTION_REGEX
+            .find_iter(v)
+            .map(|ding| ding.as_str())
+            .collect::<Vec<&str>>();
+        if mentions.is_empty() {
             return;
         }
 
-        let regex = Regex::new(r"[@<].*[>]+").unwrap();
-        let out = if let Some(val) = regex.captures(v) {
-            match val.get(0) {
-                Some(n) => n.as_str(),
-                _ => return,
-            }
-        } else {
-            return;
-        };
-
-        let mention = out.to_string();
-        map.insert(k.to_string(), mention);
+        found.push((k.to_string(), mentions.join(" ")));
     });
 
-    if map.is_empty() {
+    if found.is_empty() {
         return None;
     }
-    Some(map)
+    Some(found)
 }
 
 /// Takes a mention in the form of `@<nick https://example.com/twtxt.txt>`
-/// and reduces it to just the nickname.
+/// and reduces it to just the nickname. Only the first mention is
+/// considered, and mentions of the form `@<url>` have no nickname.
 ///
 /// # Examples
 /// ```
@@ -141,70 +167,30 @@ pub fn mentions(twtxt: &str) -> Option<BTreeMap<String, String>> {
 /// assert_eq!(mention, "nickname");
 /// ```
 pub fn mention_to_nickname(line: &str) -> Option<String> {
-    let regex = Regex::new(r"[@<].*[>]+").unwrap();
-    let mention = if let Some(val) = regex.captures(line) {
-        match val.get(0) {
-            Some(n) => n.as_str(),
-            _ => return None,
-        }
-    } else {
-        return None;
-    };
-
-    let mention_trimmed = mention[2..mention.len() - 1].to_string();
-    let mention_split = mention_trimmed.split(' ').collect::<Vec<&str>>();
-    Some(mention_split[0].into())
+    let captures = MENTION_REGEX.captures(line)?;
+    captures.get(1).map(|nick| nick.as_str().to_string())
 }
 
-/// Parses out `#tags` from each tweet, returning a `std::collections::BTreeMap<String, String>`
-/// with the timestamp as the key, and the tag as the value.
-pub fn tags(twtxt: &str) -> Option<BTreeMap<String, String>> {
-    let statuses = if let Some(val) = statuses(&twtxt) {
-        val
-    } else {
-        return None;
-    };
-    let mut map = BTreeMap::new();
+/// Parses out `#tags` from each tweet, returning a list of
+/// `(timestamp, tags)` pairs, sorted by timestamp, with the tags
+/// of each tweet separated by spaces.
+pub fn tags(twtxt: &str) -> Option<Vec<(String, String)>> {
+    let statuses = statuses(twtxt)?;
+    let mut found = Vec::new();
     statuses.iter().for_each(|(k, v)| {
-        if !v.contains('#') {
+        // A '#' inside a word, such as "issue#12", isn't a tag.
+        let tags = crate::find_tags(v).collect::<Vec<&str>>();
+        if tags.is_empty() {
             return;
         }
 
-        let regex = Regex::new(r"(^|\s)#[^\s]+").unwrap();
-        let tag: Vec<(String, String)> = regex
-            .find_iter(v)
-            .map(|ding| (k.clone(), ding.as_str().to_string()))
-            .collect();
-
-        let tags: Vec<(String, String)> = tag
-            .iter()
-            .map(|(k, v)| {
-                let v = v
-                    .chars()
-                    .map(|c| {
-                        if c.is_whitespace() {
-                            return "".into();
-                        }
-                        c.to_string()
-                    })
-                    .collect::<String>();
-                (k.clone(), v)
-            })
-            .collect();
-
-        let mut tag_group = String::new();
-        tags.iter().for_each(|(_, v)| {
-            tag_group.push_str(v);
-            tag_group.push_str(" ");
-        });
-
-        map.insert(k.to_string(), tag_group[..tag_group.len() - 1].to_string());
+        found.push((k.to_string(), tags.join(" ")));
     });
 
-    if map.is_empty() {
+    if found.is_empty() {
         return None;
     }
-    Some(map)
+    Some(found)
 }
 
 #[cfg(test)]
@@ -218,21 +204,86 @@ mod tests {
         let twtxt = "2019.09.09\tHey @<gbmor https://gbmor.dev/twtxt.txt>!";
         let mention = mention_to_nickname(twtxt).unwrap();
         assert_eq!("gbmor", mention);
+
+        let twtxt = "2019.09.09\tHey @<https://gbmor.dev/twtxt.txt>!";
+        assert_eq!(None, mention_to_nickname(twtxt));
+    }
+
+    #[test]
+    fn get_multiple_mentions() {
+        let twtxt = "test\t@<foo https://foo.example.com> and @<bar https://bar.example.com>";
+        let mention_map = mentions(twtxt).unwrap();
+        assert_eq!(
+            "@<foo https://foo.example.com> @<bar https://bar.example.com>",
+            &mention_map[0].1
+        );
     }
 
     #[test]
     fn get_tags() {
         let tag_map = tags("test\t#test").unwrap();
-        assert!("#test" == &tag_map["test"]);
+        assert!("#test" == &tag_map[0].1);
 
         let tag_map = tags("test\tsome other #test here").unwrap();
-        assert!("#test" == &tag_map["test"]);
+        assert!("#test" == &tag_map[0].1);
 
         let tag_map = tags("test\tsome other #test").unwrap();
-        assert!("#test" == &tag_map["test"]);
+        assert!("#test" == &tag_map[0].1);
 
         let tag_map = tags("test\tsome #test goes #here").unwrap();
-        assert!("#test #here" == &tag_map["test"]);
+        assert!("#test #here" == &tag_map[0].1);
+
+        assert!(tags("test\tsee issue#12").is_none());
+    }
+
+    #[test]
+    fn same_second_statuses() {
+        let twtxt = "2020-01-02T00:00:00Z\tlater #b\n\
+                     2020-01-01T00:00:00Z\tone #a\n\
+                     2020-01-01T00:00:00Z\ttwo #a";
+        let found = statuses(twtxt).unwrap();
+        let bodies = found
+            .iter()
+            .map(|(_, body)| body.as_str())
+            .collect::<Vec<&str>>();
+        assert_eq!(vec!["one #a", "two #a", "later #b"], bodies);
+        assert_eq!(3, tags(twtxt).unwrap().len());
+
+        let twtxt = "2019.09.09\tundated\n\
+                     2020-01-01T09:00:00Z\tlate\n\
+                     2020-01-01T10:00:00+02:00\tearly";
+        let found = statuses(twtxt).unwrap();
+        let bodies = found
+            .iter()
+            .map(|(_, body)| body.as_str())
+            .collect::<Vec<&str>>();
+        assert_eq!(vec!["early", "late", "undated"], bodies);
+    }
+
+    #[test]
+    fn parse_timestamps() {
+        let ts = timestamp("2020-01-01T10:00:00+02:00").unwrap();
+        assert_eq!(7200, ts.offset().local_minus_utc());
+        assert!(timestamp("2020.01.01").is_err());
+    }
+
+    #[test]
+    fn hash_keeps_offset() {
+        let url = "https://example.com/twtxt.txt";
+        let utc = timestamp("2020-01-01T08:00:00Z").unwrap();
+        let local = timestamp("2020-01-01T10:00:00+02:00").unwrap();
+        let fractional = timestamp("2020-01-01T08:00:00.123Z").unwrap();
+        assert_eq!(7, hash(url, &utc, "hi").len());
+        assert_ne!(hash(url, &utc, "hi"), hash(url, &local, "hi"));
+        assert_eq!(hash(url, &utc, "hi"), hash(url, &fractional, "hi"));
+        assert_eq!("xtcfk3a", hash(url, &local, "hi"));
+    }
+
+    #[test]
+    fn metadata_ignores_tweets() {
+        let twtxt = "# nick = foo\n2020-01-01T00:00:00Z\turl = https://example.com\n";
+        assert_eq!("foo", metadata(twtxt, "nick").unwrap());
+        assert!(metadata(twtxt, "url").is_err());
     }
 
     #[test]
@@ -0,0 +1,329 @@
+//! A streaming parser for `twtxt.txt` files, reading one line at a time
+//! so that even very large feeds are processed in constant memory.
+
+use std::collections::BTreeMap;
+use std::io::{self, BufRead, Read};
+use std::str::FromStr;
+
+use crate::fetch::FetchOptions;
+use crate::report::Problem;
+use crate::{parse, Error, Result, Tweet};
+
+/// A line of a `twtxt.txt` file.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Item {
+    /// A `# key = value` metadata comment. `line` is the comment exactly
+    /// as it was written.
+    Metadata {
+        key: String,
+        value: String,
+        line: String,
+    },
+    /// Any other comment, including the `#`.
+    Comment(String),
+    /// An empty or whitespace-only line.
+    Blank(String),
+    /// A tweet.
+    Tweet(Tweet),
+}
+
+/// Reads a `twtxt.txt` file line by line, yielding each as an `Item`.
+///
+/// Lines that can't be parsed are yielded as `Error::MalformedLine` and
+/// parsing carries on with the next line, so callers choose whether to
+/// skip them or stop. Lines longer than the default
+/// `FetchOptions::max_line_length` aren't read into memory, and are
+/// yielded as `Problem::LineTooLong`. Failing to read from the underlying
+/// reader ends the iteration.
+///
+/// # Examples
+/// ```
+/// # use rustwtxt::parser::{Item, Parser};
+/// let contents = "# nick = foo\n2020-01-01T00:00:00Z\thello\n";
+/// let tweets = Parser::new(contents.as_bytes())
+///     .filter_map(|item| match item {
+///         Ok(Item::Tweet(tweet)) => Some(tweet),
+///         _ => None,
+///     })
+///     .collect::<Vec<_>>();
+/// assert_eq!("hello", tweets[0].body());
+/// ```
+#[derive(Debug)]
+pub struct Parser<R> {
+    reader: R,
+    buf: Vec<u8>,
+    max_line_length: usize,
+    line: usize,
+    ending: &'static str,
+    done: bool,
+}
+
+impl<R: BufRead> Parser<R> {
+    /// Creates a parser reading from any buffered reader.
+    pub fn new(reader: R) -> Parser<R> {
+        Parser {
+            reader,
+            buf: Vec::new(),
+            max_line_length: FetchOptions::default().max_line_length,
+            line: 0,
+            ending: "",
+            done: false,
+        }
+    }
+
+    /// Sets the most bytes a line may have, not counting the `\n` that
+    /// ends it.
+    pub fn with_max_line_length(mut self, max_line_length: usize) -> Parser<R> {
+        self.max_line_length = max_line_length;
+        self
+    }
+
+    /// The number of the last line read, counting from 1.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    /// Reads the rest of the file, keeping only the newest `count` tweets
+    /// and skipping malformed lines. The tweets are returned oldest first.
+    ///
+    /// # Examples
+    /// ```
+    /// # use rustwtxt::parser::Parser;
+    /// let contents = "2020-01-03T00:00:00Z\tthree\n\
+    ///                 2020-01-01T00:00:00Z\tone\n\
+    ///                 2020-01-02T00:00:00Z\ttwo\n";
+    /// let newest = Parser::new(contents.as_bytes()).newest(2).unwrap();
+    /// assert_eq!("two", newest[0].body());
+    /// assert_eq!("three", newest[1].body());
+    /// ```
+    pub fn newest(self, count: usize) -> Result<Vec<Tweet>> {
+        let mut newest = BTreeMap::new();
+        for (seq, item) in self.enumerate() {
+            let tweet = match item {
+                Ok(Item::Tweet(tweet)) => tweet,
+                Ok(_) | Err(Error::MalformedLine { .. }) => continue,
+                Err(err) => return Err(err),
+            };
+            newest.insert((tweet.timestamp(), seq), tweet);
+            if newest.len() > count {
+                newest.pop_first();
+            }
+        }
+        Ok(newest.into_values().collect())
+    }
+
+    // How the last line read ended: `\r\n`, `\n`, or nothing at all at
+    // the end of a file without a trailing newline.
+    pub(crate) fn ending(&self) -> &'static str {
+        self.ending
+    }
+
+    // Reads past the rest of a line that's too long, a buffer at a time,
+    // returning how it ended.
+    fn skip_line(&mut self) -> io::Result<&'static str> {
+        let mut last = self.buf.last().copied();
+        loop {
+            let available = match self.reader.fill_buf() {
+                Ok(available) => available,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            };
+            if available.is_empty() {
+                return Ok("");
+            }
+
+            let (used, ended) = match available.iter().position(|byte| *byte == b'\n') {
+                Some(0) => (1, true),
+                Some(end) => {
+                    last = Some(available[end - 1]);
+                    (end + 1, true)
+                }
+                None => {
+                    last = available.last().copied();
+                    (available.len(), false)
+                }
+            };
+            self.reader.consume(used);
+            if ended {
+                return Ok(if last == Some(b'\r') { "\r\n" } else { "\n" });
+            }
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for Parser<R> {
+    type Item = Result<Item>;
+
+    fn next(&mut self) -> Option<Result<Item>> {
+        if self.done {
+            return None;
+        }
+
+        self.buf.clear();
+        let limit = self.max_line_length.saturating_add(1) as u64;
+        match (&mut self.reader)
+            .take(limit)
+            .read_until(b'\n', &mut self.buf)
+        {
+            Ok(0) => {
+                self.done = true;
+                return None;
+            }
+            Ok(_) => {}
+            Err(err) => {
+                self.done = true;
+                return Some(Err(Error::Io(err)));
+            }
+        }
+        self.line += 1;
+
+        if self.buf.len() > self.max_line_length && !self.buf.ends_with(b"\n") {
+            let content = String::from_utf8_lossy(&self.buf[..self.max_line_length]).into_owned();
+            self.ending = match self.skip_line() {
+                Ok(ending) => ending,
+                Err(err) => {
+                    self.done = true;
+                    return Some(Err(Error::Io(err)));
+                }
+            };
+            return Some(Err(Error::MalformedLine {
+                line: self.line,
+                content,
+                problem: Problem::LineTooLong,
+            }));
+        }
+
+        let mut line = match std::str::from_utf8(&self.buf) {
+            Ok(line) => line,
+            Err(_) => {
+                self.done = true;
+                let err = io::Error::new(io::ErrorKind::InvalidData, "Not valid UTF-8");
+                return Some(Err(Error::Io(err)));
+            }
+        };
+        self.ending = "";
+        if let Some(stripped) = line.strip_suffix('\n') {
+            self.ending = "\n";
+            line = stripped;
+            if let Some(stripped) = line.strip_suffix('\r') {
+                self.ending = "\r\n";
+                line = stripped;
+            }
+        }
+
+        Some(parse_line(line, self.line))
+    }
+}
+
+fn parse_line(line: &str, number: usize) -> Result<Item> {
+    if line.starts_with('#') {
+        return Ok(match parse::metadata_field(line) {
+            Some((key, value)) => Item::Metadata {
+                key: key.into(),
+                value: value.into(),
+                line: line.into(),
+            },
+            None => Item::Comment(line.into()),
+        });
+    }
+    if line.trim().is_empty() {
+        return Ok(Item::Blank(line.into()));
+    }
+
+    let problem = if line.contains('\t') {
+        match Tweet::from_str(line) {
+            Ok(tweet) => return Ok(Item::Tweet(tweet)),
+            Err(_) => Problem::BadTimestamp,
+        }
+    } else {
+        Problem::MissingTab
+    };
+    Err(Error::MalformedLine {
+        line: number,
+        content: line.into(),
+        problem,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn items() {
+        let contents = "# nick = foo\r\n# hello\r\n \r\nnope\r\n2020-01-01T00:00:00Z\thi";
+        let mut parser = Parser::new(contents.as_bytes());
+
+        match parser.next() {
+            Some(Ok(Item::Metadata { key, value, line })) => {
+                assert_eq!(("nick", "foo", "# nick = foo"), (&*key, &*value, &*line));
+            }
+            other => panic!("unexpected item: {:?}", other),
+        }
+        assert_eq!("\r\n", parser.ending());
+        assert_eq!(
+            Item::Comment("# hello".into()),
+            parser.next().unwrap().unwrap()
+        );
+        assert_eq!(Item::Blank(" ".into()), parser.next().unwrap().unwrap());
+        match parser.next() {
+            Some(Err(Error::MalformedLine { line, problem, .. })) => {
+                assert_eq!((4, Problem::MissingTab), (line, problem));
+            }
+            other => panic!("unexpected item: {:?}", other),
+        }
+        match parser.next() {
+            Some(Ok(Item::Tweet(tweet))) => assert_eq!("hi", tweet.body()),
+            other => panic!("unexpected item: {:?}", other),
+        }
+        assert_eq!("", parser.ending());
+        assert!(parser.next().is_none());
+    }
+
+    #[test]
+    fn stops_on_read_errors() {
+        let contents: &[u8] = b"# nick = foo\n\xff\xfe\n2020-01-01T00:00:00Z\thi\n";
+        let items = Parser::new(contents).collect::<Vec<_>>();
+        assert_eq!(2, items.len());
+        assert!(matches!(items[1], Err(Error::Io(_))));
+    }
+
+    #[test]
+    fn newest_tweets() {
+        let contents = "2020-01-01T00:00:00Z\tone\nbad line\n2020-01-01T00:00:00Z\tagain\n";
+        let newest = Parser::new(contents.as_bytes()).newest(5).unwrap();
+        assert_eq!(2, newest.len());
+        assert!(Parser::new(contents.as_bytes())
+            .newest(0)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn long_lines() {
+        let contents = format!(
+            "# nick = foo\n2020-01-01T00:00:00Z\t{}\r\n2020-01-02T00:00:00Z\thi\n",
+            "x".repeat(100)
+        );
+        let mut parser = Parser::new(contents.as_bytes()).with_max_line_length(32);
+
+        assert!(matches!(parser.next(), Some(Ok(Item::Metadata { .. }))));
+        match parser.next() {
+            Some(Err(Error::MalformedLine {
+                line,
+                content,
+                problem,
+            })) => {
+                assert_eq!((2, Problem::LineTooLong), (line, problem));
+                assert_eq!(32, content.len());
+            }
+            other => panic!("unexpected item: {:?}", other),
+        }
+        assert_eq!("\r\n", parser.ending());
+        match parser.next() {
+            Some(Ok(Item::Tweet(tweet))) => assert_eq!("hi", tweet.body()),
+            other => panic!("unexpected item: {:?}", other),
+        }
+        assert!(parser.next().is_none());
+    }
+}
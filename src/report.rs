@@ -0,0 +1,116 @@
+//! Options controlling how strictly a `twtxt.txt` file is parsed, and
+//! the report of any problems found in it.
+
+use std::fmt;
+
+/// How a `twtxt.txt` file should be parsed.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct ParseOptions {
+    /// Fail on the first problem instead of reporting it and carrying on.
+    pub strict: bool,
+}
+
+impl ParseOptions {
+    /// Skip malformed lines, listing them in the `ParseReport`.
+    pub fn lenient() -> ParseOptions {
+        ParseOptions { strict: false }
+    }
+
+    /// Fail with `Error::MalformedLine` on the first problem.
+    pub fn strict() -> ParseOptions {
+        ParseOptions { strict: true }
+    }
+}
+
+/// What was wrong with a line.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Problem {
+    /// The line has a tab, but what comes before it isn't
+    /// an `RFC 3339` timestamp. The line is skipped.
+    BadTimestamp,
+    /// The line isn't a comment but has no tab separating
+    /// the timestamp from the tweet. The line is skipped.
+    MissingTab,
+    /// The tweet contains control characters other than tabs.
+    /// The tweet is kept.
+    ControlCharacters,
+    /// Another tweet was posted at the same instant.
+    DuplicateTimestamp,
+    /// The line is longer than the parser allows. Only its start is kept,
+    /// and the line is skipped.
+    LineTooLong,
+}
+
+impl Problem {
+    /// Whether the line is left out of the parsed tweets.
+    pub fn skips_line(self) -> bool {
+        match self {
+            Problem::BadTimestamp | Problem::MissingTab | Problem::LineTooLong => true,
+            Problem::ControlCharacters | Problem::DuplicateTimestamp => false,
+        }
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let problem = match self {
+            Problem::BadTimestamp => "Bad timestamp",
+            Problem::MissingTab => "Missing tab",
+            Problem::ControlCharacters => "Control characters",
+            Problem::DuplicateTimestamp => "Duplicate timestamp",
+            Problem::LineTooLong => "Line too long",
+        };
+        write!(f, "{}", problem)
+    }
+}
+
+/// A problem found on a single line. Lines are numbered from 1.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Diagnostic {
+    pub line: usize,
+    pub content: String,
+    pub problem: Problem,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Line {}: {}: {:?}",
+            self.line, self.problem, self.content
+        )
+    }
+}
+
+/// Every problem found while parsing a `twtxt.txt` file.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct ParseReport {
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl ParseReport {
+    pub(crate) fn push(&mut self, line: usize, content: &str, problem: Problem) {
+        self.diagnostics.push(Diagnostic {
+            line,
+            content: content.into(),
+            problem,
+        });
+    }
+
+    /// Whether the file parsed without any problems.
+    pub fn is_clean(&self) -> bool {
+        self.diagnostics.is_empty()
+    }
+
+    /// Every problem found, in the order of the lines they were found on.
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    /// The lines that were left out of the parsed tweets.
+    pub fn skipped(&self) -> impl Iterator<Item = &Diagnostic> {
+        self.diagnostics
+            .iter()
+            .filter(|diagnostic| diagnostic.problem.skips_line())
+    }
+}
@@ -0,0 +1,61 @@
+//! Tiny HTTP servers for tests, listening on a random local port.
+
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::sync::mpsc;
+
+// Answers up to `count` requests with `respond(request)`, returning the
+// address of the server as an `http://` URL.
+fn listen<F>(count: usize, mut respond: F) -> String
+where
+    F: FnMut(&str) -> String + Send + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    std::thread::spawn(move || {
+        for stream in listener.incoming().take(count) {
+            let mut stream = stream.unwrap();
+            let mut buf = [0; 1024];
+            let len = stream.read(&mut buf).unwrap_or(0);
+            let response = respond(&String::from_utf8_lossy(&buf[..len]));
+            let _ = stream.write_all(response.as_bytes());
+        }
+    });
+    url
+}
+
+/// Answers each request with the next response, sending the lowercased
+/// request headers back over the channel. Returns the URL to request.
+pub(crate) fn serve(responses: Vec<String>) -> (String, mpsc::Receiver<String>) {
+    let (tx, rx) = mpsc::channel();
+    let count = responses.len();
+    let mut responses = responses.into_iter();
+    let url = listen(count, move |request| {
+        let _ = tx.send(request.to_lowercase());
+        responses.next().unwrap_or_default()
+    });
+    (format!("{}/twtxt.txt", url), rx)
+}
+
+/// Serves each file at its path, answering anything else with a 404,
+/// and returns the URL of the server.
+pub(crate) fn serve_files(files: &'static [(&'static str, &'static str)]) -> String {
+    listen(usize::MAX, move |request| {
+        let path = request.split(' ').nth(1).unwrap_or_default();
+        match files.iter().find(|(file, _)| *file == path) {
+            Some((_, body)) => response("200 OK", "", body),
+            None => response("404 Not Found", "", ""),
+        }
+    })
+}
+
+/// An HTTP response closing the connection. Each header must end in `\r\n`.
+pub(crate) fn response(status: &str, headers: &str, body: &str) -> String {
+    format!(
+        "HTTP/1.1 {}\r\n{}Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        headers,
+        body.len(),
+        body
+    )
+}
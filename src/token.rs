@@ -0,0 +1,231 @@
+//! Splits the body of a tweet into the pieces a renderer cares about,
+//! so each can be formatted without parsing the body again.
+
+use crate::{Mention, Subject, LINE_SEPARATOR};
+
+// Schemes recognized in bare links.
+const SCHEMES: [&str; 4] = ["https://", "http://", "gemini://", "gopher://"];
+
+/// A piece of a tweet body.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Token<'a> {
+    /// Plain text.
+    Text(&'a str),
+    /// `@<nick url>` or `@<url>`.
+    Mention(Mention),
+    /// A `#tag`, including the `#`.
+    Tag(&'a str),
+    /// A markdown link, `[text](url)`, or a bare URL, where the text
+    /// and URL are the same.
+    Link { text: &'a str, url: &'a str },
+    /// A markdown image, `![alt](url)`.
+    Image { alt: &'a str, url: &'a str },
+    /// The `(#hash)` or `(@<nick url>)` subject at the start of the tweet.
+    Subject(Subject),
+    /// Inline code or a code block, without the backticks.
+    Code(&'a str),
+    /// A `U+2028` line separator in a multi-line tweet.
+    LineBreak,
+}
+
+/// Splits a tweet body into tokens. Anything that isn't recognized is
+/// returned as `Token::Text`.
+///
+/// # Examples
+/// ```
+/// # use rustwtxt::token::{self, Token};
+/// let tokens = token::tokenize("see [this](https://example.com) #neat");
+/// assert_eq!(
+///     vec![
+///         Token::Text("see "),
+///         Token::Link { text: "this", url: "https://example.com" },
+///         Token::Text(" "),
+///         Token::Tag("#neat"),
+///     ],
+///     tokens
+/// );
+/// ```
+pub fn tokenize(body: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    let mut text_start = 0;
+    let mut idx = 0;
+    // The subject may follow whitespace, which is kept as text.
+    let mut subject = Subject::parse_with_end(body);
+    let subject_start = body.len() - body.trim_start().len();
+
+    while idx < body.len() {
+        let rest = &body[idx..];
+        // U+2028 counts as whitespace here.
+        let at_word_start = body[..idx].chars().last().is_none_or(char::is_whitespace);
+
+        let found = if idx == subject_start && subject.is_some() {
+            subject
+                .take()
+                .map(|(subject, end)| (Token::Subject(subject), end - idx))
+        } else if rest.starts_with(LINE_SEPARATOR) {
+            Some((Token::LineBreak, LINE_SEPARATOR.len_utf8()))
+        } else if rest.starts_with('`') {
+            code(rest)
+        } else if rest.starts_with("![") {
+            markdown(&rest[1..]).map(|(alt, url, len)| (Token::Image { alt, url }, len + 1))
+        } else if rest.starts_with('[') {
+            markdown(rest).map(|(text, url, len)| (Token::Link { text, url }, len))
+        } else if rest.starts_with("@<") {
+            Mention::at_start(rest, idx).map(|mention| {
+                let len = mention.span.len();
+                (Token::Mention(mention), len)
+            })
+        } else if at_word_start && rest.starts_with('#') {
+            tag(rest).map(|tag| (Token::Tag(tag), tag.len()))
+        } else if at_word_start {
+            bare_link(rest).map(|url| (Token::Link { text: url, url }, url.len()))
+        } else {
+            None
+        };
+
+        match found {
+            Some((token, len)) => {
+                if text_start < idx {
+                    tokens.push(Token::Text(&body[text_start..idx]));
+                }
+                tokens.push(token);
+                idx += len;
+                text_start = idx;
+            }
+            None => idx += rest.chars().next().map_or(1, char::len_utf8),
+        }
+    }
+
+    if text_start < body.len() {
+        tokens.push(Token::Text(&body[text_start..]));
+    }
+    tokens
+}
+
+// ```block``` or `inline`.
+fn code(rest: &str) -> Option<(Token<'_>, usize)> {
+    let fence = if rest.starts_with("```") { "```" } else { "`" };
+    let inner = &rest[fence.len()..];
+    let end = inner.find(fence)?;
+    if end == 0 {
+        return None;
+    }
+    Some((Token::Code(&inner[..end]), fence.len() * 2 + end))
+}
+
+// [text](url), returning the text, the URL and the length consumed.
+fn markdown(rest: &str) -> Option<(&str, &str, usize)> {
+    let close = rest.find("](")?;
+    let text = &rest[1..close];
+    if text.contains(']') || text.contains(LINE_SEPARATOR) {
+        return None;
+    }
+
+    let after = &rest[close + 2..];
+    let end = after.find(')')?;
+    let url = after[..end].trim();
+    if url.is_empty() || url.contains(char::is_whitespace) {
+        return None;
+    }
+    Some((text, url, close + 2 + end + 1))
+}
+
+fn tag(rest: &str) -> Option<&str> {
+    let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+    if end < 2 {
+        return None;
+    }
+    Some(&rest[..end])
+}
+
+fn bare_link(rest: &str) -> Option<&str> {
+    let scheme = SCHEMES.iter().find(|scheme| rest.starts_with(*scheme))?;
+    let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+    // Punctuation ending a sentence isn't part of the URL.
+    let url = rest[..end].trim_end_matches(|c| ".,:;!?)".contains(c));
+    if url.len() <= scheme.len() {
+        return None;
+    }
+    Some(url)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokens_cover_the_body() {
+        let body = "  (#abcdefg) hi\u{2028}#there `x`";
+        let joined = tokenize(body)
+            .iter()
+            .map(|token| match token {
+                Token::Text(text) | Token::Tag(text) => text.to_string(),
+                Token::Subject(Subject::Hash(hash)) => format!("(#{})", hash),
+                Token::Code(code) => format!("`{}`", code),
+                Token::LineBreak => LINE_SEPARATOR.to_string(),
+                other => panic!("unexpected token: {:?}", other),
+            })
+            .collect::<String>();
+        assert_eq!(body, joined);
+        assert_eq!(Token::Text("  "), tokenize(body)[0]);
+    }
+
+    #[test]
+    fn every_kind_of_token() {
+        let body =
+            "(#abcdefg) hey @<foo https://foo.example.com/twtxt.txt>, try `cargo run`\u{2028}\
+                    ![a cat](https://example.com/cat.png) at https://example.com/cats. #cats";
+        let tokens = tokenize(body);
+        assert_eq!(Token::Subject(Subject::Hash("abcdefg".into())), tokens[0]);
+        assert_eq!(Token::Text(" hey "), tokens[1]);
+        match &tokens[2] {
+            Token::Mention(mention) => {
+                assert_eq!(Some("foo"), mention.nick.as_deref());
+                assert_eq!(
+                    "@<foo https://foo.example.com/twtxt.txt>",
+                    &body[mention.span.clone()]
+                );
+            }
+            other => panic!("unexpected token: {:?}", other),
+        }
+        assert_eq!(
+            vec![
+                Token::Text(", try "),
+                Token::Code("cargo run"),
+                Token::LineBreak,
+                Token::Image {
+                    alt: "a cat",
+                    url: "https://example.com/cat.png"
+                },
+                Token::Text(" at "),
+                Token::Link {
+                    text: "https://example.com/cats",
+                    url: "https://example.com/cats"
+                },
+                Token::Text(". "),
+                Token::Tag("#cats"),
+            ],
+            &tokens[3..]
+        );
+    }
+
+    #[test]
+    fn plain_text() {
+        assert_eq!(vec![Token::Text("just text")], tokenize("just text"));
+        assert!(tokenize("").is_empty());
+    }
+
+    #[test]
+    fn malformed_markup_is_text() {
+        let body = "a[b](c d) issue#12 `open @<nope> [x]( ) # ![";
+        assert_eq!(vec![Token::Text(body)], tokenize(body));
+    }
+
+    #[test]
+    fn code_blocks() {
+        assert_eq!(
+            vec![Token::Code("fn main() {}"), Token::Text(" ok")],
+            tokenize("```fn main() {}``` ok")
+        );
+    }
+}
@@ -1,4 +1,3 @@
-use rustwtxt;
 use rustwtxt::parse;
 
 #[test]
@@ -14,7 +13,7 @@ fn end_to_end() {
     assert!(mentions.len() > 1);
 
     let mention = "@<nick url>";
-    let mention_nick = parse::mention_to_nickname(&mention).unwrap();
+    let mention_nick = parse::mention_to_nickname(mention).unwrap();
     assert_eq!("nick", mention_nick);
 
     let twtxt = rustwtxt::Twtxt::from("https://gbmor.dev/twtxt.txt").unwrap();
@@ -0,0 +1,108 @@
+// Every public parsing function must survive arbitrary input from remote
+// feeds, returning errors rather than panicking.
+
+use std::str::FromStr;
+
+use proptest::prelude::*;
+use rustwtxt::{parse, token, Mention, Metadata, ParseOptions, Subject, Tweet, TweetRef, Twtxt};
+
+const URL: &str = "https://example.com/twtxt.txt";
+
+// Bodies heavy in the characters the tokenizer, mention and subject
+// parsers care about.
+fn body() -> impl Strategy<Value = String> {
+    prop_oneof![
+        ".{0,60}",
+        "[@<>#()\\[\\]!`a-z :/.\t\u{2028}\u{7}é]{0,60}",
+        "\\(#[a-z0-9<> ]{0,12}\\)[ a-z@<>]{0,20}",
+    ]
+}
+
+fn line() -> impl Strategy<Value = String> {
+    prop_oneof![
+        "# ?[a-z]{0,8} ?=? ?.{0,20}",
+        "[0-9T:.+Z-]{0,25}\t.{0,40}",
+        body().prop_map(|body| format!("2020-01-01T00:00:00+01:00\t{}", body)),
+        body().prop_map(|body| format!("2020-01-01T00:00:00Z\t{}", body)),
+        ".{0,30}",
+        Just(String::new()),
+    ]
+}
+
+fn twtxt() -> impl Strategy<Value = String> {
+    prop::collection::vec(line(), 0..20).prop_map(|lines| lines.join("\n"))
+}
+
+fn check_everything(input: &str) {
+    let _ = Twtxt::parse(input, URL);
+    let _ = Twtxt::parse_with(input, URL, &ParseOptions::strict());
+    let _ = Twtxt::from_reader(input.as_bytes(), URL);
+    let _ = Metadata::parse(input);
+    let _ = parse::metadata(input, "nick");
+    let _ = parse::statuses(input);
+    let _ = parse::mentions(input);
+    let _ = parse::tags(input);
+    TweetRef::all(input).for_each(|tweet| {
+        let _ = tweet.tags().count();
+        let _ = tweet.subject();
+    });
+    input.lines().for_each(|line| {
+        let _ = Tweet::from_str(line).map(|tweet| {
+            let _ = tweet.tokens();
+            let _ = tweet.hash(URL);
+        });
+        let _ = parse::metadata_field(line);
+        let _ = parse::mention_to_nickname(line);
+        let _ = parse::timestamp(line);
+        let _ = Mention::find_all(line);
+        let _ = Subject::parse(line);
+        let _ = token::tokenize(line);
+    });
+}
+
+proptest! {
+    #[test]
+    fn arbitrary_strings(input in ".*") {
+        check_everything(&input);
+    }
+
+    #[test]
+    fn twtxt_like_files(input in twtxt()) {
+        check_everything(&input);
+    }
+
+    #[test]
+    fn round_trip(input in twtxt()) {
+        let input = format!("# nick = foo\n{}", input);
+        let twtxt = Twtxt::parse(&input, URL).unwrap();
+        prop_assert_eq!(input, twtxt.to_string());
+    }
+}
+
+#[test]
+fn known_panics() {
+    [
+        "",
+        "\t",
+        "no tab",
+        "2020-01-01T00:00:00Z\t",
+        "test\tsee issue#12",
+        "@<",
+        "@<>",
+        "@<é>",
+        "2019.09.09\t@<a>",
+        "(#)",
+        "(@<x>)",
+        "`",
+        "``````",
+        "[",
+        "![",
+        "[](",
+        "#",
+        "# =",
+        "#=",
+        "https://",
+    ]
+    .iter()
+    .for_each(|input| check_everything(input));
+}